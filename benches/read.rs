@@ -0,0 +1,76 @@
+//! Throughput of the read paths, run with `cargo +nightly bench --features nightly`. Each
+//! iteration reads the whole stream through a fresh reader, so every byte is a miss first time
+//! round.
+
+#![feature(test)]
+
+extern crate test;
+
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use saturating_reader::saturating_reader::SaturatingReader;
+use test::{black_box, Bencher};
+
+const LEN: usize = 16 * 1024 * 1024;
+const READ_SIZE: usize = 64 * 1024;
+
+fn data() -> Vec<u8> {
+    (0..LEN).map(|i| i as u8).collect()
+}
+
+/// Reads through the whole stream in pieces of `chunk`.
+fn read_sequential(b: &mut Bencher, chunk: usize) {
+    let data = data();
+    let mut buf = vec![0; chunk];
+    b.bytes = LEN as u64;
+    b.iter(|| {
+        let mut reader = SaturatingReader::with_capacity(READ_SIZE, Cursor::new(data.as_slice()));
+        while reader.read(&mut buf).unwrap() > 0 {}
+        black_box(&buf);
+    });
+}
+
+/// Large reads miss straight into the caller's buffer.
+#[bench]
+fn sequential_large_reads(b: &mut Bencher) {
+    read_sequential(b, 1024 * 1024);
+}
+
+/// Small reads go through readahead, and are mostly served from the buffers.
+#[bench]
+fn sequential_small_reads(b: &mut Bencher) {
+    read_sequential(b, 4 * 1024);
+}
+
+/// Reads of a read size each, visiting every piece of the stream once in a scattered order.
+#[bench]
+fn random_reads(b: &mut Bencher) {
+    let data = data();
+    let pieces = LEN / READ_SIZE;
+    let mut buf = vec![0; READ_SIZE];
+    b.bytes = LEN as u64;
+    b.iter(|| {
+        let mut reader = SaturatingReader::with_capacity(READ_SIZE, Cursor::new(data.as_slice()));
+        // Stepping by a number coprime with the count hits every piece
+        for i in (0..pieces).map(|i| i * 97 % pieces) {
+            reader
+                .seek(SeekFrom::Start((i * READ_SIZE) as u64))
+                .unwrap();
+            reader.read_exact(&mut buf).unwrap();
+        }
+        black_box(&buf);
+    });
+}
+
+/// Reading the inner reader directly, for comparison.
+#[bench]
+fn baseline(b: &mut Bencher) {
+    let data = data();
+    let mut buf = vec![0; 1024 * 1024];
+    b.bytes = LEN as u64;
+    b.iter(|| {
+        let mut reader = Cursor::new(data.as_slice());
+        while reader.read(&mut buf).unwrap() > 0 {}
+        black_box(&buf);
+    });
+}
@@ -0,0 +1,71 @@
+//! Backs a [`SaturatingReader`] with a custom [`RangeFetch`] source. Here the source is an
+//! in-memory map of fixed size chunks, standing in for something like an object store, but the
+//! same approach works for any backend which can be asked for ranges of bytes.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, SeekFrom},
+};
+
+use saturating_reader::{
+    fetch::{FetchReader, RangeFetch},
+    saturating_reader::SaturatingReader,
+};
+
+const CHUNK_SIZE: u64 = 64;
+
+/// Data split up into chunks, keyed by the offset each chunk starts at.
+struct ChunkStore {
+    chunks: BTreeMap<u64, Vec<u8>>,
+    fetches: usize,
+}
+
+impl ChunkStore {
+    fn new(data: &[u8]) -> Self {
+        let chunks = data
+            .chunks(CHUNK_SIZE as usize)
+            .enumerate()
+            .map(|(i, chunk)| (i as u64 * CHUNK_SIZE, chunk.to_vec()))
+            .collect();
+
+        Self { chunks, fetches: 0 }
+    }
+}
+
+impl RangeFetch for ChunkStore {
+    fn fetch(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        self.fetches += 1;
+        println!("Fetching {len} bytes at {offset}");
+
+        // Gather up the chunks covering the range, trimming the first and last
+        let end = offset + len as u64;
+        let mut data = vec![];
+        for (&start, chunk) in self.chunks.range(offset - offset % CHUNK_SIZE..end) {
+            let from = offset.saturating_sub(start) as usize;
+            let to = ((end - start) as usize).min(chunk.len());
+            data.extend_from_slice(&chunk[from..to]);
+        }
+
+        Ok(data)
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let data = (0..1000).map(|i| (i % 256) as u8).collect::<Vec<_>>();
+    let mut reader = SaturatingReader::with_capacity(256, FetchReader::new(ChunkStore::new(&data)));
+
+    let mut buf = [0; 100];
+    reader.seek(SeekFrom::Start(500))?;
+    reader.read_exact(&mut buf)?;
+    assert_eq!(buf.as_slice(), &data[500..600]);
+
+    // This is served entirely from the cache, without going back to the store
+    reader.seek(SeekFrom::Start(550))?;
+    reader.read_exact(&mut buf[..50])?;
+    assert_eq!(&buf[..50], &data[550..600]);
+
+    let fetches = reader.into_inner().into_inner().fetches;
+    println!("Made {fetches} fetches");
+
+    Ok(())
+}
@@ -0,0 +1,263 @@
+use std::{
+    io::SeekFrom,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+use crate::{cache::Cache, error::SaturatingError};
+
+/// What the reader is waiting on from the inner reader, if anything.
+#[derive(Debug)]
+enum State {
+    Idle,
+    // Moving the inner reader to the start of a gap we need to fetch
+    FetchSeek { offset: u64, len: usize },
+    // Reading a gap from the inner reader
+    FetchRead { offset: u64, buf: Vec<u8> },
+    // Deferring an end-relative seek to the inner reader
+    EndSeek,
+}
+
+/// The async counterpart to [`crate::saturating_reader::SaturatingReader`], maintaining internal
+/// buffers of everything it reads.
+///
+/// Reads which can be served from the buffers complete immediately, without polling the inner
+/// reader. Only once the cursor reaches data which isn't buffered does the reader seek and read
+/// the inner reader, keeping what it fetched for later.
+#[derive(Debug)]
+pub struct AsyncSaturatingReader<R: AsyncRead + AsyncSeek + Unpin> {
+    inner: R,
+    cache: Cache,
+    cursor_pos: u64,
+    bufread_size: usize,
+    state: State,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncSaturatingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(8 * 1024, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        Self {
+            inner,
+            cache: Cache::default(),
+            cursor_pos: 0,
+            bufread_size: capacity,
+            state: State::Idle,
+        }
+    }
+
+    /// Returns the ranges `[start, end)` held in the internal buffers, ordered by start.
+    pub fn cached_ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.cache.cached_ranges()
+    }
+
+    /// Consumes the reader, returning the inner reader. Note that the cursor position may not be
+    /// the same as the outer reader, as it is updated lazily during reads.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncRead for AsyncSaturatingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            match &mut this.state {
+                State::Idle => {
+                    // Hand over whatever is buffered straight away, even if it's a short read
+                    let (num_bytes_copied, gap) = this
+                        .cache
+                        .read_cached(this.cursor_pos, buf.initialize_unfilled());
+                    let Some((gap_start, gap_end)) = gap.filter(|_| num_bytes_copied == 0) else {
+                        buf.advance(num_bytes_copied);
+                        this.cache
+                            .touch(this.cursor_pos, this.cursor_pos + num_bytes_copied as u64);
+                        this.cursor_pos += num_bytes_copied as u64;
+                        return Poll::Ready(Ok(()));
+                    };
+
+                    // Otherwise we'll have to fetch the gap
+                    Pin::new(&mut this.inner).start_seek(SeekFrom::Start(gap_start))?;
+                    this.state = State::FetchSeek {
+                        offset: gap_start,
+                        len: ((gap_end - gap_start) as usize).max(this.bufread_size),
+                    };
+                }
+                State::FetchSeek { offset, len } => {
+                    let (offset, len) = (*offset, *len);
+                    let result = ready!(Pin::new(&mut this.inner).poll_complete(cx));
+                    if let Err(e) = result {
+                        this.state = State::Idle;
+                        return Poll::Ready(Err(e));
+                    }
+                    this.state = State::FetchRead {
+                        offset,
+                        buf: vec![0; len],
+                    };
+                }
+                State::FetchRead {
+                    offset,
+                    buf: fetch_buf,
+                } => {
+                    let mut read_buf = ReadBuf::new(fetch_buf);
+                    let result = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read_buf));
+                    let num_bytes_read = read_buf.filled().len();
+                    if num_bytes_read > 0 {
+                        this.cache.add_buffer(*offset, read_buf.filled());
+                    }
+                    this.state = State::Idle;
+                    result?;
+
+                    // The inner reader is exhausted, so leave the caller's buffer as it is
+                    if num_bytes_read == 0 {
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+                State::EndSeek => {
+                    return Poll::Ready(Err(std::io::Error::other(
+                        "Read attempted while a seek is in progress.",
+                    )));
+                }
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncSeek for AsyncSaturatingReader<R> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let this = self.get_mut();
+        if !matches!(this.state, State::Idle) {
+            return Err(std::io::Error::other(
+                "Seek attempted while another operation is in progress.",
+            ));
+        }
+
+        match position {
+            // For start/current, don't seek the underlying reader. It will be handled in
+            // poll_read() if needed.
+            SeekFrom::Start(p) => this.cursor_pos = p,
+            SeekFrom::Current(p) => {
+                this.cursor_pos = this.cursor_pos.checked_add_signed(p).ok_or(
+                    SaturatingError::SeekUnderflow {
+                        position: this.cursor_pos,
+                        offset: p,
+                    },
+                )?;
+            }
+            // Our inner might not support seeking from end, so defer to its implementation
+            // instead.
+            SeekFrom::End(_) => {
+                Pin::new(&mut this.inner).start_seek(position)?;
+                this.state = State::EndSeek;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        let this = self.get_mut();
+        if let State::EndSeek = this.state {
+            let result = ready!(Pin::new(&mut this.inner).poll_complete(cx));
+            this.state = State::Idle;
+            this.cursor_pos = result?;
+        }
+
+        Poll::Ready(Ok(this.cursor_pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Cursor, SeekFrom},
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf};
+
+    use super::AsyncSaturatingReader;
+
+    /// Wraps an async reader, counting how many times it gets polled for reads.
+    struct CountingReader<R> {
+        inner: R,
+        polls: usize,
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.polls += 1;
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl<R: AsyncSeek + Unpin> AsyncSeek for CountingReader<R> {
+        fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+            Pin::new(&mut self.inner).start_seek(position)
+        }
+
+        fn poll_complete(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<u64>> {
+            Pin::new(&mut self.inner).poll_complete(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reread_from_cache() {
+        let reader = CountingReader {
+            inner: Cursor::new((0..=255).collect::<Vec<_>>()),
+            polls: 0,
+        };
+        let mut bufreader = AsyncSaturatingReader::with_capacity(1, reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf.as_slice(), (0..64).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.polls, 1);
+
+        // Served entirely from the cache
+        bufreader.seek(SeekFrom::Start(0)).await.unwrap();
+        bufreader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf.as_slice(), (0..64).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.polls, 1);
+
+        // Partially cached, so only the rest is fetched
+        bufreader.seek(SeekFrom::Start(32)).await.unwrap();
+        bufreader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf.as_slice(), (32..96).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.polls, 2);
+        assert_eq!(bufreader.cached_ranges().collect::<Vec<_>>(), vec![(0, 96)]);
+    }
+
+    #[tokio::test]
+    async fn test_read_to_end() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = AsyncSaturatingReader::with_capacity(100, reader);
+
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, (0..=255).collect::<Vec<_>>());
+
+        let pos = bufreader.seek(SeekFrom::End(-10)).await.unwrap();
+        assert_eq!(pos, 246);
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, (246..=255).collect::<Vec<_>>());
+    }
+}
@@ -1,57 +1,386 @@
+// With the bytes feature, data is reference counted so clones and slices of a buffer share it
+#[cfg(feature = "bytes")]
+type Data = bytes::Bytes;
+#[cfg(not(feature = "bytes"))]
+type Data = Vec<u8>;
+
+/// Where the data held by [`Buffer`]s lives. The default, [`HeapStore`], gives each buffer its own
+/// heap allocation, but a store could just as well hand out space in an arena or a memory mapped
+/// scratch file, to keep large caches out of the heap.
+///
+/// Every buffer holds on to a clone of the store its data came from, so stores should be cheap
+/// handles onto whatever they share. The provided methods only use [`Self::alloc`], [`Self::get`]
+/// and [`Self::free`], which is simple but copies more than it needs to, so stores which can do
+/// better should override them.
+pub trait BufferStore: Clone + Default + std::fmt::Debug {
+    /// A single buffer's data. The default value holds nothing, and is what's left behind when
+    /// data is taken out of a buffer.
+    type Data: Default + std::fmt::Debug;
+
+    /// Allocates space holding a copy of `data`.
+    fn alloc(&self, data: &[u8]) -> Self::Data;
+
+    /// Allocates space holding `data`, for when it's already been put together in a `Vec`. Stores
+    /// which can take the `Vec` over as it is should, to save copying it.
+    fn alloc_vec(&self, data: Vec<u8>) -> Self::Data {
+        self.alloc(&data)
+    }
+
+    /// Returns the bytes held by `data`.
+    fn get<'a>(&'a self, data: &'a Self::Data) -> &'a [u8];
+
+    /// Releases `data`, which won't be used again.
+    fn free(&self, data: Self::Data);
+
+    /// Makes a copy of `data`, as for cloning a buffer.
+    fn clone_data(&self, data: &Self::Data) -> Self::Data {
+        self.alloc(self.get(data))
+    }
+
+    /// Splits `data` in two at `at`, leaving `[0, at)` in place and returning the rest.
+    fn split_off(&self, data: &mut Self::Data, at: usize) -> Self::Data {
+        let (head, tail) = self.get(data).split_at(at);
+        let (head, tail) = (self.alloc(head), self.alloc(tail));
+        self.free(std::mem::replace(data, head));
+
+        tail
+    }
+
+    /// Copies `bytes` over `data` starting at `at`.
+    fn write(&self, data: &mut Self::Data, at: usize, bytes: &[u8]) {
+        let mut buf = self.get(data).to_vec();
+        buf[at..at + bytes.len()].copy_from_slice(bytes);
+        let new = self.alloc(&buf);
+        self.free(std::mem::replace(data, new));
+    }
+
+    /// Adds `bytes` onto the end of `data`.
+    fn append(&self, data: &mut Self::Data, bytes: &[u8]) {
+        let new = self.alloc(&[self.get(data), bytes].concat());
+        self.free(std::mem::replace(data, new));
+    }
+
+    /// Frees any memory `data` holds beyond the bytes themselves.
+    fn shrink_to_fit(&self, _data: &mut Self::Data) {}
+
+    /// Consumes `data`, returning its bytes.
+    fn take_vec(&self, data: Self::Data) -> Vec<u8> {
+        let bytes = self.get(&data).to_vec();
+        self.free(data);
+
+        bytes
+    }
+}
+
+/// Keeps each buffer's data in its own heap allocation, which is reference counted with the bytes
+/// feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStore;
+
+// Conversions into the data type only do anything when it's Bytes
+#[cfg_attr(not(feature = "bytes"), allow(clippy::useless_conversion))]
+impl BufferStore for HeapStore {
+    type Data = Data;
+
+    fn alloc(&self, data: &[u8]) -> Data {
+        data.to_vec().into()
+    }
+
+    fn alloc_vec(&self, data: Vec<u8>) -> Data {
+        data.into()
+    }
+
+    fn get<'a>(&'a self, data: &'a Data) -> &'a [u8] {
+        data
+    }
+
+    fn free(&self, _data: Data) {}
+
+    fn clone_data(&self, data: &Data) -> Data {
+        data.clone()
+    }
+
+    fn split_off(&self, data: &mut Data, at: usize) -> Data {
+        data.split_off(at)
+    }
+
+    fn write(&self, data: &mut Data, at: usize, bytes: &[u8]) {
+        // Shared data has to be copied before we can change it
+        let mut buf = Vec::from(std::mem::take(data));
+        buf[at..at + bytes.len()].copy_from_slice(bytes);
+        *data = buf.into();
+    }
+
+    // Converting unshared data back into a Vec doesn't copy it, so this only reallocates as often
+    // as a growing Vec would
+    fn append(&self, data: &mut Data, bytes: &[u8]) {
+        let mut buf = Vec::from(std::mem::take(data));
+        buf.extend_from_slice(bytes);
+        *data = buf.into();
+    }
+
+    // Shared data can't tell how much it's holding on to, so it's left as it is
+    fn shrink_to_fit(&self, _data: &mut Data) {
+        #[cfg(not(feature = "bytes"))]
+        _data.shrink_to_fit();
+    }
+
+    // With the bytes feature this only copies if the data is shared with another buffer
+    fn take_vec(&self, data: Data) -> Vec<u8> {
+        data.into()
+    }
+}
+
 #[derive(Debug)]
-pub struct Buffer {
+pub struct Buffer<S: BufferStore = HeapStore> {
     start: u64,
     end: u64, // exclusive
-    data: Vec<u8>,
+    data: S::Data,
+    store: S,
+    last_access: u64,
+    // CRC32 of the data, taken whenever it changes
+    #[cfg(feature = "checksum")]
+    checksum: u32,
+}
+
+impl<S: BufferStore> Clone for Buffer<S> {
+    fn clone(&self) -> Self {
+        Self {
+            start: self.start,
+            end: self.end,
+            data: self.store.clone_data(&self.data),
+            store: self.store.clone(),
+            last_access: self.last_access,
+            #[cfg(feature = "checksum")]
+            checksum: self.checksum,
+        }
+    }
+}
+
+impl<S: BufferStore> Drop for Buffer<S> {
+    fn drop(&mut self) {
+        self.store.free(std::mem::take(&mut self.data));
+    }
+}
+
+/// Buffers are equal if they cover the same range with the same data. When they were last accessed
+/// doesn't matter.
+impl<S: BufferStore> PartialEq for Buffer<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.start == other.start && self.end == other.end && self.data() == other.data()
+    }
+}
+
+impl<S: BufferStore> Eq for Buffer<S> {}
+
+// What a buffer looks like on the wire, before we've checked it's valid
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RawBuffer<D> {
+    start: u64,
+    end: u64,
+    data: D,
+}
+
+#[cfg(feature = "serde")]
+impl<S: BufferStore> serde::Serialize for Buffer<S> {
+    fn serialize<Se: serde::Serializer>(&self, serializer: Se) -> Result<Se::Ok, Se::Error> {
+        RawBuffer {
+            start: self.start,
+            end: self.end,
+            data: self.data(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, S: BufferStore> serde::Deserialize<'de> for Buffer<S> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawBuffer::<Vec<u8>>::deserialize(deserializer)?;
+        if raw.start >= raw.end {
+            return Err(serde::de::Error::custom(
+                "Buffer must represent a valid range.",
+            ));
+        }
+        if raw.data.len() as u64 != raw.end - raw.start {
+            return Err(serde::de::Error::custom(
+                "Buffer data doesn't match its range.",
+            ));
+        }
+
+        Ok(Self::from_vec_in(raw.start, raw.data, S::default()))
+    }
 }
 
 impl Buffer {
     pub fn new(start: u64, end: u64) -> Self {
+        Self::new_in(start, end, HeapStore)
+    }
+
+    pub fn from_slice(start: u64, buf: &[u8]) -> Self {
+        Self::from_slice_in(start, buf, HeapStore)
+    }
+
+    // Creates a buffer holding data from start onwards, which shares it rather than copying it
+    #[cfg(feature = "bytes")]
+    pub fn from_bytes(start: u64, data: impl Into<bytes::Bytes>) -> Self {
+        let data = data.into();
+        Self {
+            start,
+            end: start + data.len() as u64,
+            #[cfg(feature = "checksum")]
+            checksum: crc32fast::hash(&data),
+            data,
+            store: HeapStore,
+            last_access: 0,
+        }
+    }
+
+    // Returns a handle to the requested range if it exists in the buffer, sharing the buffer's
+    // data rather than copying it
+    #[cfg(feature = "bytes")]
+    pub fn get_range_bytes(&self, offset: u64, length: u64) -> Option<bytes::Bytes> {
+        if !self.contains_range(offset, length) {
+            return None;
+        }
+
+        let start = (offset - self.start) as usize;
+        Some(self.data.slice(start..start + length as usize))
+    }
+}
+
+impl<S: BufferStore> Buffer<S> {
+    // Creates a zeroed buffer covering [start, end), with its data held in the given store
+    pub fn new_in(start: u64, end: u64, store: S) -> Self {
         assert!(start < end, "Buffer must represent a valid range.");
 
+        Self::from_vec_in(start, vec![0; (end - start) as usize], store)
+    }
+
+    // Creates a buffer holding a copy of buf from start onwards, with its data held in the given
+    // store
+    pub fn from_slice_in(start: u64, buf: &[u8], store: S) -> Self {
         Self {
             start,
-            end,
-            data: vec![0; (end - start) as usize],
+            end: start + buf.len() as u64,
+            data: store.alloc(buf),
+            store,
+            last_access: 0,
+            #[cfg(feature = "checksum")]
+            checksum: crc32fast::hash(buf),
         }
     }
 
-    pub fn from_slice(start: u64, buf: &[u8]) -> Self {
+    // Creates a buffer holding buf from start onwards, handing it over to the store as it is
+    // rather than copying it
+    pub(crate) fn from_vec_in(start: u64, buf: Vec<u8>, store: S) -> Self {
         Self {
             start,
             end: start + buf.len() as u64,
-            data: buf.to_vec(),
+            #[cfg(feature = "checksum")]
+            checksum: crc32fast::hash(&buf),
+            data: store.alloc_vec(buf),
+            store,
+            last_access: 0,
         }
     }
 
-    // Consumes both buffers, merging them
+    // Consumes both buffers, merging them. Panics if they don't overlap or touch.
     pub fn merge(self, other: Self) -> Self {
-        assert!(self.overlaps(&other), "buffers do not overlap");
+        match self.try_merge(other) {
+            Ok(merged) => merged,
+            Err(_) => panic!("buffers do not overlap or touch"),
+        }
+    }
+
+    // Consumes both buffers, merging them if they overlap or touch. Otherwise both are handed
+    // back as they were, which is worth the large error type.
+    #[allow(clippy::result_large_err)]
+    pub fn try_merge(self, other: Self) -> Result<Self, (Self, Self)> {
+        if !self.touches(&other) {
+            return Err((self, other));
+        }
+        // Other's data wins where they overlap, so if it covers everything there's nothing to copy
+        if other.start <= self.start && self.end <= other.end {
+            return Ok(other);
+        }
 
         // Create new buffer object
         let start = self.start.min(other.start);
         let end = self.end.max(other.end);
-        let mut new = Self::new(start, end);
+        let mut data = vec![0; (end - start) as usize];
 
         // Copy data from self over
-        new.data[(self.start - start) as usize..(self.end - start) as usize]
-            .copy_from_slice(&self.data);
+        data[(self.start - start) as usize..(self.end - start) as usize]
+            .copy_from_slice(self.data());
         // Copy data from other over
-        new.data[(other.start - start) as usize..(other.end - start) as usize]
-            .copy_from_slice(&other.data);
+        data[(other.start - start) as usize..(other.end - start) as usize]
+            .copy_from_slice(other.data());
 
-        new
+        Ok(Self::from_vec_in(start, data, self.store.clone()))
+    }
+
+    // Splits the buffer in two at the given offset. This buffer keeps [start, at) and the returned
+    // one holds [at, end).
+    pub fn split_off(&mut self, at: u64) -> Self {
+        assert!(
+            self.start < at && at < self.end,
+            "split must leave both buffers non-empty"
+        );
+
+        let data = self
+            .store
+            .split_off(&mut self.data, (at - self.start) as usize);
+        let other = Self {
+            start: at,
+            end: self.end,
+            #[cfg(feature = "checksum")]
+            checksum: crc32fast::hash(self.store.get(&data)),
+            data,
+            store: self.store.clone(),
+            last_access: self.last_access,
+        };
+        self.end = at;
+        #[cfg(feature = "checksum")]
+        {
+            self.checksum = crc32fast::hash(self.data());
+        }
+
+        other
+    }
+
+    // Marks the buffer as having been used at the given point in time
+    pub(crate) fn touch(&mut self, tick: u64) {
+        self.last_access = tick;
+    }
+
+    pub(crate) fn last_access(&self) -> u64 {
+        self.last_access
     }
 
     // Check if there is any intersection between the ranges [self.start, self.end) and [other.start, other.end)
-    // Also if they are touching end to end
-    pub fn overlaps(&self, other: &Buffer) -> bool {
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    // Check if the buffers overlap or sit end to end, with nothing in between, so they can be
+    // merged into one
+    pub fn touches(&self, other: &Self) -> bool {
         self.start <= other.end && other.start <= self.end
     }
 
-    // Checks if the requested read exists fully within the buffer
+    // Checks if the offset lies within [self.start, self.end)
+    pub fn contains_offset(&self, offset: u64) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    // Checks if the requested read exists fully within the buffer. Ranges which overflow can't
+    // possibly be contained.
     fn contains_range(&self, offset: u64, length: u64) -> bool {
-        self.start <= offset && offset + length <= self.end
+        offset
+            .checked_add(length)
+            .is_some_and(|end| self.start <= offset && end <= self.end)
     }
 
     // Returns a reference to the requested range if it exists in the buffer.
@@ -62,7 +391,93 @@ impl Buffer {
 
         let start = offset - self.start;
         let end = start + length;
-        Some(&self.data[start as usize..end as usize])
+        Some(&self.data()[start as usize..end as usize])
+    }
+
+    // Returns the data from the requested offset to the end of the buffer, if the offset lies
+    // within it. Unlike get_range, this serves as much as the buffer has, so it can be used for
+    // partial hits.
+    pub fn get_available_from(&self, offset: u64) -> Option<&[u8]> {
+        if !self.contains_offset(offset) {
+            return None;
+        }
+
+        Some(&self.data()[(offset - self.start) as usize..])
+    }
+
+    // Copies over whatever part of [offset, offset + data.len()) lies within the buffer, leaving
+    // the rest of the buffer as it was
+    pub(crate) fn overwrite(&mut self, offset: u64, data: &[u8]) {
+        let start = offset.max(self.start);
+        let end = offset.saturating_add(data.len() as u64).min(self.end);
+        if start >= end {
+            return;
+        }
+
+        self.store.write(
+            &mut self.data,
+            (start - self.start) as usize,
+            &data[(start - offset) as usize..(end - offset) as usize],
+        );
+        #[cfg(feature = "checksum")]
+        {
+            self.checksum = crc32fast::hash(self.data());
+        }
+    }
+
+    // Adds data onto the end of the buffer, growing it in place rather than merging in a new one
+    pub(crate) fn append(&mut self, data: &[u8]) {
+        self.store.append(&mut self.data, data);
+        self.end += data.len() as u64;
+        #[cfg(feature = "checksum")]
+        {
+            // Carry on from the old checksum, so appending doesn't rehash what was already there
+            let mut hasher = crc32fast::Hasher::new_with_initial(self.checksum);
+            hasher.update(data);
+            self.checksum = hasher.finalize();
+        }
+    }
+
+    // Checks the data still matches the checksum taken when it was last changed
+    #[cfg(feature = "checksum")]
+    pub(crate) fn verify(&self) -> bool {
+        crc32fast::hash(self.data()) == self.checksum
+    }
+
+    // Without checksums there's nothing to check against, so the data is taken to be fine
+    #[cfg(not(feature = "checksum"))]
+    pub(crate) fn verify(&self) -> bool {
+        true
+    }
+
+    // Changes the data without updating the checksum, as if it had been corrupted in memory
+    #[cfg(all(test, feature = "checksum"))]
+    pub(crate) fn corrupt(&mut self, offset: u64, data: &[u8]) {
+        let checksum = self.checksum;
+        self.overwrite(offset, data);
+        self.checksum = checksum;
+    }
+
+    // Frees any memory held beyond the data itself, such as what's left over after a split
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.store.shrink_to_fit(&mut self.data);
+    }
+
+    // Returns all of the data held in the buffer
+    pub fn data(&self) -> &[u8] {
+        self.store.get(&self.data)
+    }
+
+    // Consumes the buffer, returning its data. With the bytes feature this only copies if the
+    // data is shared with another buffer.
+    pub fn into_data(mut self) -> Vec<u8> {
+        let data = std::mem::take(&mut self.data);
+        self.store.take_vec(data)
+    }
+
+    // Returns a reader over all of the data held in the buffer, without copying it
+    pub fn as_read(&self) -> impl std::io::Read + '_ {
+        self.data()
     }
 
     // Returns the range of data this buffer represents
@@ -71,9 +486,33 @@ impl Buffer {
     }
 }
 
+// Removes the range [other.0, other.1) from [range.0, range.1), returning whatever is left over in
+// order. This is nothing, one range, or two ranges if other falls in the middle.
+pub fn subtract(range: (u64, u64), other: (u64, u64)) -> Vec<(u64, u64)> {
+    let (start, end) = range;
+    let (other_start, other_end) = other;
+
+    // Nothing in common, so the range is left as it is
+    if other_end <= start || end <= other_start || other_start >= other_end {
+        return vec![range];
+    }
+
+    let mut remainder = vec![];
+    if start < other_start {
+        remainder.push((start, other_start));
+    }
+    if other_end < end {
+        remainder.push((other_end, end));
+    }
+
+    remainder
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::buffer::Buffer;
+    use std::io::Read;
+
+    use crate::buffer::{subtract, Buffer};
 
     #[test]
     fn test_overlaps() {
@@ -84,27 +523,179 @@ mod tests {
         assert!(buf1.overlaps(&buf2));
         assert!(!buf1.overlaps(&buf3));
         assert!(buf3.overlaps(&buf3));
-        assert!(buf1.overlaps(&buf4));
+        assert!(!buf1.overlaps(&buf4));
+    }
+
+    #[test]
+    fn test_touches() {
+        let buf1 = Buffer::new(0, 10);
+        let buf2 = Buffer::new(10, 20);
+        assert!(buf1.touches(&buf2));
+        assert!(buf2.touches(&buf1));
+        assert!(!buf1.overlaps(&buf2));
+
+        let buf3 = Buffer::new(5, 15);
+        assert!(buf1.touches(&buf3));
+        assert!(buf1.overlaps(&buf3));
+        assert!(!buf1.touches(&Buffer::new(11, 20)));
+
+        // Touching buffers can still be merged
+        let merged = Buffer::from_slice(0, &[1; 10]).merge(Buffer::from_slice(10, &[2; 10]));
+        assert_eq!(merged.range(), (0, 20));
+    }
+
+    #[test]
+    fn test_contains_offset() {
+        let buf1 = Buffer::new(10, 20);
+        assert!(buf1.contains_offset(10));
+        assert!(buf1.contains_offset(19));
+        assert!(!buf1.contains_offset(20));
+        assert!(!buf1.contains_offset(9));
     }
 
     #[test]
     fn test_merge() {
-        let mut buf1 = Buffer::new(0, 10);
-        buf1.data.copy_from_slice(&(0..10).collect::<Vec<_>>());
+        let buf1 = Buffer::from_slice(0, &(0..10).collect::<Vec<_>>());
 
-        let mut buf2 = Buffer::new(5, 15);
-        buf2.data.copy_from_slice(&(5..15).collect::<Vec<_>>());
+        let buf2 = Buffer::from_slice(5, &(5..15).collect::<Vec<_>>());
 
         let new_buf1 = buf1.merge(buf2);
-        assert_eq!(new_buf1.start, 0);
-        assert_eq!(new_buf1.end, 15);
-        assert_eq!(new_buf1.data, (0..15).collect::<Vec<_>>())
+        assert_eq!(
+            new_buf1,
+            Buffer::from_slice(0, &(0..15).collect::<Vec<_>>())
+        );
     }
 
     #[test]
-    fn test_get_range() {
+    fn test_eq() {
+        let buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+        let mut buf2 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+        buf2.last_access = 5;
+        assert_eq!(buf1, buf2);
+
+        assert_ne!(buf1, Buffer::from_slice(10, &[0; 10]));
+        assert_ne!(buf1, Buffer::from_slice(11, &(10..20).collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn test_try_merge() {
+        let buf1 = Buffer::from_slice(0, &(0..10).collect::<Vec<_>>());
+
+        // Overlapping
+        let merged = buf1
+            .clone()
+            .try_merge(Buffer::from_slice(5, &(5..15).collect::<Vec<_>>()))
+            .unwrap();
+        assert_eq!(merged, Buffer::from_slice(0, &(0..15).collect::<Vec<_>>()));
+
+        // Touching
+        let merged = buf1
+            .clone()
+            .try_merge(Buffer::from_slice(10, &(10..20).collect::<Vec<_>>()))
+            .unwrap();
+        assert_eq!(merged, Buffer::from_slice(0, &(0..20).collect::<Vec<_>>()));
+
+        // Disjoint
+        let buf2 = Buffer::from_slice(11, &[1; 5]);
+        assert_eq!(
+            buf1.clone().try_merge(buf2.clone()).unwrap_err(),
+            (buf1, buf2)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "buffers do not overlap or touch")]
+    fn test_merge_disjoint() {
+        Buffer::new(0, 10).merge(Buffer::new(11, 20));
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+
+        let buf2 = buf1.split_off(14);
+        assert_eq!(buf1, Buffer::from_slice(10, &[10, 11, 12, 13]));
+        assert_eq!(buf2, Buffer::from_slice(14, &(14..20).collect::<Vec<_>>()));
+    }
+
+    #[test]
+    #[cfg(not(feature = "bytes"))]
+    fn test_shrink_to_fit() {
+        let mut buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+        buf1.split_off(14);
+        assert!(buf1.data.capacity() > buf1.data.len());
+
+        buf1.shrink_to_fit();
+        assert_eq!(buf1.data.capacity(), buf1.data.len());
+        assert_eq!(buf1.data, vec![10, 11, 12, 13]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_split_off_at_end() {
         let mut buf1 = Buffer::new(10, 20);
-        buf1.data.copy_from_slice(&(10..20).collect::<Vec<_>>());
+        buf1.split_off(20);
+    }
+
+    #[test]
+    fn test_subtract() {
+        // Middle
+        assert_eq!(subtract((0, 100), (40, 60)), vec![(0, 40), (60, 100)]);
+        // Prefix only left over
+        assert_eq!(subtract((0, 100), (40, 100)), vec![(0, 40)]);
+        assert_eq!(subtract((0, 100), (40, 150)), vec![(0, 40)]);
+        // Suffix only left over
+        assert_eq!(subtract((0, 100), (0, 60)), vec![(60, 100)]);
+        assert_eq!(subtract((50, 100), (0, 60)), vec![(60, 100)]);
+        // Fully covered
+        assert_eq!(subtract((0, 100), (0, 100)), vec![]);
+        assert_eq!(subtract((40, 60), (0, 100)), vec![]);
+        // Disjoint, including touching end to end
+        assert_eq!(subtract((0, 100), (100, 150)), vec![(0, 100)]);
+        assert_eq!(subtract((50, 100), (0, 50)), vec![(50, 100)]);
+        assert_eq!(subtract((0, 100), (200, 300)), vec![(0, 100)]);
+        // Subtracting an empty range
+        assert_eq!(subtract((0, 100), (50, 50)), vec![(0, 100)]);
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let mut buf = Buffer::from_slice(10, &[0; 10]);
+        buf.overwrite(5, &[1; 10]);
+        buf.overwrite(18, &[2; 10]);
+        buf.overwrite(12, &[3; 2]);
+        buf.overwrite(30, &[4; 2]);
+        assert_eq!(buf.data(), &[1, 1, 3, 3, 1, 0, 0, 0, 2, 2]);
+        assert_eq!(buf.range(), (10, 20));
+    }
+
+    #[test]
+    fn test_append() {
+        let mut buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+        buf1.append(&(20..30).collect::<Vec<_>>());
+        assert_eq!(buf1, Buffer::from_slice(10, &(10..30).collect::<Vec<_>>()));
+        #[cfg(feature = "checksum")]
+        assert!(buf1.verify());
+    }
+
+    #[test]
+    fn test_into_data() {
+        let buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+        assert_eq!(buf1.into_data(), (10..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_as_read() {
+        let buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+
+        let mut data = vec![];
+        buf1.as_read().read_to_end(&mut data).unwrap();
+        assert_eq!(data, (10..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_get_range() {
+        let buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
 
         let range1 = buf1.get_range(11, 4);
         assert_eq!(range1, Some(vec![11, 12, 13, 14].as_slice()));
@@ -115,4 +706,83 @@ mod tests {
         let range4 = buf1.get_range(10, 10);
         assert_eq!(range4, Some((10..20).collect::<Vec<_>>().as_slice()));
     }
+
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn test_from_bytes() {
+        let data = bytes::Bytes::from((10..20).collect::<Vec<_>>());
+        let buf1 = Buffer::from_bytes(10, data.clone());
+        assert_eq!(buf1, Buffer::from_slice(10, &(10..20).collect::<Vec<_>>()));
+        assert_eq!(buf1.data().as_ptr(), data.as_ptr());
+
+        // Merging in a buffer which adds nothing keeps the data where it was
+        let merged = Buffer::from_slice(12, &[0; 4]).merge(buf1);
+        assert_eq!(merged.data().as_ptr(), data.as_ptr());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_get_range_bytes() {
+        let buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+
+        let range1 = buf1.get_range_bytes(12, 4).unwrap();
+        let range2 = buf1.get_range_bytes(12, 4).unwrap();
+        assert_eq!(range1, vec![12, 13, 14, 15]);
+        // Both share the buffer's allocation
+        assert_eq!(range1.as_ptr(), range2.as_ptr());
+        assert_eq!(range1.as_ptr(), buf1.get_range(12, 4).unwrap().as_ptr());
+        assert_eq!(buf1.get_range_bytes(18, 4), None);
+    }
+
+    #[test]
+    fn test_get_range_overflow() {
+        let buf1 = Buffer::new(10, 20);
+
+        assert_eq!(buf1.get_range(u64::MAX - 1, 10), None);
+        assert_eq!(buf1.get_range(15, u64::MAX), None);
+    }
+
+    #[test]
+    fn test_get_available_from() {
+        let buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+
+        assert_eq!(
+            buf1.get_available_from(10),
+            Some((10..20).collect::<Vec<_>>().as_slice())
+        );
+        assert_eq!(
+            buf1.get_available_from(15),
+            Some(vec![15, 16, 17, 18, 19].as_slice())
+        );
+        assert_eq!(buf1.get_available_from(19), Some(vec![19].as_slice()));
+        assert_eq!(buf1.get_available_from(9), None);
+        assert_eq!(buf1.get_available_from(20), None);
+        assert_eq!(buf1.get_available_from(25), None);
+
+        // Asking for more than is there still gives what there is
+        assert_eq!(buf1.get_range(15, 40), None);
+        assert_eq!(buf1.get_available_from(15).unwrap().len(), 5);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let buf1 = Buffer::from_slice(10, &(10..20).collect::<Vec<_>>());
+
+        let json = serde_json::to_string(&buf1).unwrap();
+        let buf2: Buffer = serde_json::from_str(&json).unwrap();
+        assert_eq!(buf2, buf1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_rejects_invalid() {
+        // Data doesn't match the range
+        let json = r#"{"start":10,"end":20,"data":[1,2,3]}"#;
+        assert!(serde_json::from_str::<Buffer>(json).is_err());
+
+        // Empty range
+        let json = r#"{"start":10,"end":10,"data":[]}"#;
+        assert!(serde_json::from_str::<Buffer>(json).is_err());
+    }
 }
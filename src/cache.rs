@@ -0,0 +1,912 @@
+use std::borrow::Cow;
+
+use crate::{
+    buffer::{subtract, Buffer, BufferStore, HeapStore},
+    stats::{CacheEvent, EventHook},
+};
+
+// Handed out in place of holes, which have no data of their own to borrow
+static ZEROS: [u8; 8 * 1024] = [0; 8 * 1024];
+
+/// A piece of a requested range, as found in the internal buffers.
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    /// Buffered data, starting at the given offset.
+    Cached(u64, &'a [u8]),
+    /// A range `[start, end)` which hasn't been buffered yet.
+    Missing(u64, u64),
+}
+
+/// How newly fetched data is combined with the buffers it overlaps or touches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// Merge into a single buffer, copying the data of everything involved.
+    #[default]
+    Always,
+    /// Keep buffers separate, only storing the parts of new data which weren't already buffered.
+    /// Nothing is copied, but reads may have to look through several buffers.
+    Never,
+    /// Merge as for [`MergePolicy::Always`], and also fetch any gap of up to this many bytes
+    /// between new data and its neighbours, so they can be merged too.
+    BridgeGaps(u64),
+}
+
+/// The set of buffers held by a reader, independent of where the data comes from.
+#[derive(Debug, Clone)]
+pub(crate) struct Cache<S: BufferStore = HeapStore> {
+    // Kept sorted by start offset and disjoint, so at most one buffer can hold any given offset.
+    // Unless the merge policy says otherwise, overlapping and touching buffers are merged on
+    // insert.
+    pub(crate) buffers: Vec<Buffer<S>>,
+    pub(crate) merge_policy: MergePolicy,
+    max_cached_bytes: Option<usize>,
+    max_buffers: Option<usize>,
+    // Ticks up on every access, so buffers can be ordered by how recently they were used
+    access_clock: u64,
+    // Ranges which eviction must leave alone
+    pinned: Vec<(u64, u64)>,
+    // Ranges known to be all zeros, which never need fetching
+    holes: Vec<(u64, u64)>,
+    pub(crate) events: EventHook,
+    // Where the data for new buffers is allocated
+    store: S,
+}
+
+// Only implemented for the default store, so tests and the like don't need to name it
+impl Default for Cache {
+    fn default() -> Self {
+        Self::with_store(None, HeapStore)
+    }
+}
+
+impl<S: BufferStore> Cache<S> {
+    /// Creates an empty cache, which holds at most `max_cached_bytes` if given.
+    pub(crate) fn new(max_cached_bytes: Option<usize>) -> Self {
+        Self::with_store(max_cached_bytes, S::default())
+    }
+
+    /// Creates an empty cache like [`Self::new`], which allocates the data for its buffers in
+    /// `store`.
+    pub(crate) fn with_store(max_cached_bytes: Option<usize>, store: S) -> Self {
+        Self {
+            buffers: Vec::new(),
+            merge_policy: MergePolicy::default(),
+            max_cached_bytes,
+            max_buffers: None,
+            access_clock: 0,
+            pinned: Vec::new(),
+            holes: Vec::new(),
+            events: EventHook::default(),
+            store,
+        }
+    }
+
+    /// Creates a cache holding the given buffers, which may be in any order. Overlapping buffers are
+    /// merged together, and empty ones are dropped.
+    pub(crate) fn from_buffers(mut buffers: Vec<Buffer<S>>) -> Self {
+        buffers.retain(|b| !b.data().is_empty());
+        buffers.sort_by_key(|b| b.range().0);
+
+        let mut cache = Self {
+            buffers,
+            ..Self::new(None)
+        };
+        cache.coalesce_adjacent();
+
+        cache
+    }
+
+    /// Adds a new buffer to the internally maintained set. Overlapping buffers are merged together
+    /// for optimisation. Empty slices are ignored. Data that's handed over in a `Vec` is kept as it
+    /// is where possible, rather than being copied. Returns how much was buffered before anything
+    /// was evicted to make room.
+    pub(crate) fn add_buffer<'a>(&mut self, offset: u64, buf: impl Into<Cow<'a, [u8]>>) -> u64 {
+        let buf = buf.into();
+        if buf.is_empty() {
+            return self.cached_bytes();
+        }
+
+        let (start, end) = (offset, offset + buf.len() as u64);
+        if self.merge_policy == MergePolicy::Never {
+            self.insert_unmerged(offset, &buf);
+        } else if let Some(last) = self.buffers.last_mut().filter(|b| b.range().1 == start) {
+            // Sequential reads land just after the last buffer, which can simply grow to take
+            // them rather than being drained and merged with a copy of everything it holds
+            last.append(&buf);
+        } else {
+            // Pull out all buffers which overlap or touch the new one. As they're sorted and
+            // disjoint, these are all next to each other.
+            let first = self.buffers.partition_point(|b| b.range().1 < start);
+            let last = self.buffers.partition_point(|b| b.range().0 <= end);
+            // Corrupted buffers are dropped rather than merged, which would give their data a
+            // fresh checksum
+            let overlapping = self.buffers.drain(first..last).filter(|b| b.verify());
+
+            // Merge the overlapping buffers
+            let new_buffer = match buf {
+                Cow::Borrowed(buf) => Buffer::from_slice_in(offset, buf, self.store.clone()),
+                Cow::Owned(buf) => Buffer::from_vec_in(offset, buf, self.store.clone()),
+            };
+            let new_buffer = overlapping.fold(new_buffer, |acc, x| acc.merge(x));
+
+            // Add the new buffer into the collection, where it belongs in the order
+            self.buffers.insert(first, new_buffer);
+        }
+        self.touch(start, end);
+
+        let cached_bytes = self.cached_bytes();
+        self.evict(start, end);
+        #[cfg(debug_assertions)]
+        self.assert_invariants();
+
+        cached_bytes
+    }
+
+    /// Checks that the buffers are laid out as everything else here expects: each is non-empty
+    /// and holds exactly as much data as its range covers, and they're sorted by start without
+    /// overlapping. Touching buffers are allowed, as the merge policy may keep them apart.
+    ///
+    /// # Panics
+    /// If any of these don't hold.
+    #[cfg(debug_assertions)]
+    pub(crate) fn assert_invariants(&self) {
+        for buffer in &self.buffers {
+            let (start, end) = buffer.range();
+            assert!(start < end, "buffer [{start}, {end}) is empty");
+            assert_eq!(
+                buffer.data().len() as u64,
+                end - start,
+                "buffer [{start}, {end}) holds the wrong amount of data"
+            );
+        }
+        for pair in self.buffers.windows(2) {
+            let ((_, prev_end), (next_start, _)) = (pair[0].range(), pair[1].range());
+            assert!(
+                prev_end <= next_start,
+                "buffer ending at {prev_end} is followed by one starting at {next_start}"
+            );
+        }
+    }
+
+    /// Adds whatever parts of `buf` aren't already buffered as buffers of their own, leaving
+    /// existing buffers untouched.
+    fn insert_unmerged(&mut self, offset: u64, buf: &[u8]) {
+        // Corrupted buffers would otherwise stop the new data from replacing them
+        #[cfg(feature = "checksum")]
+        self.drop_corrupt(offset, offset + buf.len() as u64);
+        let overlapping = self.overlapping(offset, offset + buf.len() as u64);
+        let pieces = self.buffers[overlapping.clone()].iter().fold(
+            vec![(offset, offset + buf.len() as u64)],
+            |ranges, buffer| {
+                ranges
+                    .into_iter()
+                    .flat_map(|range| subtract(range, buffer.range()))
+                    .collect()
+            },
+        );
+
+        // The new pieces fill the gaps between the overlapping buffers, so putting them all back
+        // in order only needs a sort of this stretch
+        let mut buffers = self.buffers.drain(overlapping.clone()).collect::<Vec<_>>();
+        buffers.extend(pieces.into_iter().map(|(start, end)| {
+            Buffer::from_slice_in(
+                start,
+                &buf[(start - offset) as usize..(end - offset) as usize],
+                self.store.clone(),
+            )
+        }));
+        buffers.sort_by_key(|b| b.range().0);
+        self.buffers
+            .splice(overlapping.start..overlapping.start, buffers);
+    }
+
+    /// Returns the gaps either side of the buffer holding `offset`, up until the neighbouring
+    /// buffers. There's no gap on a side without a neighbour.
+    pub(crate) fn gaps_around(&self, offset: u64) -> Vec<(u64, u64)> {
+        let i = self.buffers.partition_point(|b| b.range().0 <= offset);
+        let Some(i) = i.checked_sub(1) else {
+            return vec![];
+        };
+        let (start, end) = self.buffers[i].range();
+
+        let before = i
+            .checked_sub(1)
+            .map(|prev| (self.buffers[prev].range().1, start));
+        let after = self.buffers.get(i + 1).map(|next| (end, next.range().0));
+        before
+            .into_iter()
+            .chain(after)
+            .filter(|(start, end)| start < end)
+            .collect()
+    }
+
+    /// Marks every buffer holding part of `[start, end)` as the most recently used.
+    pub(crate) fn touch(&mut self, start: u64, end: u64) {
+        self.access_clock += 1;
+        let overlapping = self.overlapping(start, end);
+        for buffer in &mut self.buffers[overlapping] {
+            buffer.touch(self.access_clock);
+        }
+    }
+
+    /// Returns the most that's held in the buffers at once, if limited.
+    pub(crate) fn max_cached_bytes(&self) -> Option<usize> {
+        self.max_cached_bytes
+    }
+
+    /// Limits how many separate buffers are held, from the next insert onwards.
+    pub(crate) fn set_max_buffers(&mut self, max_buffers: usize) {
+        self.max_buffers = Some(max_buffers);
+    }
+
+    /// Makes room for at least `additional` more buffers without reallocating.
+    pub(crate) fn reserve_buffers(&mut self, additional: usize) {
+        self.buffers.reserve(additional);
+    }
+
+    /// Drops buffers until we're back within the memory limit and buffer count, starting with the
+    /// least recently used. The range `[keep_start, keep_end)` is never dropped, so if it belongs
+    /// to the last buffer standing, that buffer is trimmed down around it instead.
+    fn evict(&mut self, keep_start: u64, keep_end: u64) {
+        if self.max_cached_bytes.is_none() && self.max_buffers.is_none() {
+            return;
+        }
+        let max_bytes = self.max_cached_bytes.map_or(u64::MAX, |max| max as u64);
+        let max_buffers = self.max_buffers.unwrap_or(usize::MAX);
+
+        while self.cached_bytes() > max_bytes || self.buffers.len() > max_buffers {
+            // The range we're keeping was touched last, so anything older is fair game, as long
+            // as it isn't all pinned
+            let oldest = self
+                .buffers
+                .iter()
+                .filter(|b| b.last_access() < self.access_clock)
+                .map(|b| (b.last_access(), self.unpinned(b.range())))
+                .filter(|(_, unpinned)| !unpinned.is_empty())
+                .min_by_key(|(last_access, _)| *last_access);
+            let Some((_, unpinned)) = oldest else {
+                break;
+            };
+            for (start, end) in unpinned {
+                self.events.emit(CacheEvent::Evict {
+                    offset: start,
+                    len: end - start,
+                });
+                self.invalidate(start, end);
+            }
+        }
+
+        // We may still be over if the buffers we're keeping have been merged into something big.
+        // Trim from the front first, as a forward scan is the likeliest way to get here.
+        let mut excess = self.cached_bytes().saturating_sub(max_bytes);
+        if excess == 0 {
+            return;
+        }
+        let Some(i) = self.buffers.iter().position(|b| {
+            let (start, end) = b.range();
+            start < keep_end && keep_start < end
+        }) else {
+            return;
+        };
+        let (start, end) = self.buffers[i].range();
+        // Stop short of anything pinned either side of the range we're keeping
+        let keep_start = self
+            .pinned
+            .iter()
+            .filter(|&&(pin_start, pin_end)| pin_start < keep_start && start < pin_end)
+            .fold(keep_start, |keep_start, &(pin_start, _)| {
+                keep_start.min(pin_start)
+            });
+        let keep_end = self
+            .pinned
+            .iter()
+            .filter(|&&(pin_start, pin_end)| keep_end < pin_end && pin_start < end)
+            .fold(keep_end, |keep_end, &(_, pin_end)| keep_end.max(pin_end));
+        if start < keep_start {
+            let at = keep_start.min(start + excess);
+            self.buffers[i] = self.buffers[i].split_off(at);
+            excess -= at - start;
+            self.events.emit(CacheEvent::Evict {
+                offset: start,
+                len: at - start,
+            });
+        }
+        if excess > 0 && keep_end < end {
+            let at = keep_end.max(end.saturating_sub(excess));
+            self.buffers[i].split_off(at);
+            self.events.emit(CacheEvent::Evict {
+                offset: at,
+                len: end - at,
+            });
+        }
+    }
+
+    /// Returns the parts of `[start, end)` which aren't pinned.
+    fn unpinned(&self, (start, end): (u64, u64)) -> Vec<(u64, u64)> {
+        self.pinned.iter().fold(vec![(start, end)], |ranges, &pin| {
+            ranges
+                .into_iter()
+                .flat_map(|range| subtract(range, pin))
+                .collect()
+        })
+    }
+
+    /// Drops anything buffered within `[start, end)` which isn't pinned.
+    pub(crate) fn invalidate_unpinned(&mut self, start: u64, end: u64) {
+        for (start, end) in self.unpinned((start, end)) {
+            self.invalidate(start, end);
+        }
+    }
+
+    /// Stops eviction from dropping anything buffered within `[start, end)`.
+    pub(crate) fn pin(&mut self, start: u64, end: u64) {
+        if start < end {
+            self.pinned.push((start, end));
+        }
+    }
+
+    /// Lets eviction drop anything buffered within `[start, end)` again.
+    pub(crate) fn unpin(&mut self, start: u64, end: u64) {
+        self.pinned = self
+            .pinned
+            .iter()
+            .flat_map(|&pin| subtract(pin, (start, end)))
+            .collect();
+    }
+
+    /// Records that `[start, end)` holds nothing but zeros, so it never needs to be fetched.
+    pub(crate) fn mark_hole(&mut self, start: u64, end: u64) {
+        if start < end {
+            self.holes.push((start, end));
+        }
+    }
+
+    /// Returns the first hole `[start, end)` which ends after `offset`, if there is one.
+    pub(crate) fn next_hole(&self, offset: u64) -> Option<(u64, u64)> {
+        self.holes
+            .iter()
+            .filter(|&&(_, end)| offset < end)
+            .min_by_key(|&&(start, _)| start)
+            .copied()
+    }
+
+    /// Returns where the hole holding `offset` ends, or the next buffer starts if that's sooner,
+    /// if `offset` is in a hole and isn't buffered.
+    pub(crate) fn hole_end(&self, offset: u64) -> Option<u64> {
+        if self.buffer_index_for(offset).is_ok() {
+            return None;
+        }
+        let (_, hole_end) = self
+            .next_hole(offset)
+            .filter(|&(hole_start, _)| hole_start <= offset)?;
+        let next = self.buffers.partition_point(|b| b.range().0 <= offset);
+
+        Some(
+            self.buffers
+                .get(next)
+                .map_or(hole_end, |b| b.range().0.min(hole_end)),
+        )
+    }
+
+    /// Replaces whatever is buffered within `[offset, offset + data.len())` with `data`. Parts of
+    /// the range which aren't buffered are left that way.
+    pub(crate) fn overwrite(&mut self, offset: u64, data: &[u8]) {
+        let overlapping = self.overlapping(offset, offset.saturating_add(data.len() as u64));
+        for buffer in &mut self.buffers[overlapping] {
+            buffer.overwrite(offset, data);
+        }
+    }
+
+    /// Drops any buffer holding part of `[start, end)` whose data no longer matches its checksum,
+    /// so it gets fetched again rather than handed out.
+    #[cfg(feature = "checksum")]
+    pub(crate) fn drop_corrupt(&mut self, start: u64, end: u64) {
+        let overlapping = self.overlapping(start, end);
+        let first = overlapping.start;
+        let kept = self
+            .buffers
+            .drain(overlapping)
+            .filter(|b| b.verify())
+            .collect::<Vec<_>>();
+        self.buffers.splice(first..first, kept);
+    }
+
+    /// Frees any memory held beyond the buffered data itself.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.shrink_to_fit();
+        }
+        self.buffers.shrink_to_fit();
+    }
+
+    /// Drops everything held in the buffers.
+    pub(crate) fn clear(&mut self) {
+        self.buffers.clear();
+    }
+
+    /// Takes all of the buffers out, leaving the cache empty.
+    pub(crate) fn take_buffers(&mut self) -> Vec<Buffer<S>> {
+        std::mem::take(&mut self.buffers)
+    }
+
+    /// Drops any buffered data within `[start, end)`. Buffers which only partially overlap the
+    /// range are trimmed down to what's left, which splits them in two if the range falls in the
+    /// middle.
+    pub(crate) fn invalidate(&mut self, start: u64, end: u64) {
+        let buffers = std::mem::take(&mut self.buffers);
+        for mut buffer in buffers {
+            let (buffer_start, buffer_end) = buffer.range();
+            if buffer_end <= start || end <= buffer_start {
+                self.buffers.push(buffer);
+                continue;
+            }
+
+            // Keep whatever is left either side of the range
+            let after = (end < buffer_end).then(|| buffer.split_off(end));
+            if buffer_start < start {
+                buffer.split_off(start);
+                self.buffers.push(buffer);
+            }
+            self.buffers.extend(after);
+        }
+        #[cfg(debug_assertions)]
+        self.assert_invariants();
+    }
+
+    /// Merges any neighbouring buffers which touch end to end, leaving the buffered data as it is.
+    /// Buffers must be sorted, but overlapping ones are merged too.
+    pub(crate) fn coalesce_adjacent(&mut self) {
+        let buffers = std::mem::take(&mut self.buffers);
+        for buffer in buffers {
+            match self.buffers.pop() {
+                Some(last) if last.range().1 >= buffer.range().0 => {
+                    // Keep the latest access, so merging doesn't change what gets evicted first
+                    let last_access = last.last_access().max(buffer.last_access());
+                    let mut merged = last.merge(buffer);
+                    merged.touch(last_access);
+                    self.buffers.push(merged);
+                }
+                last => {
+                    self.buffers.extend(last);
+                    self.buffers.push(buffer);
+                }
+            }
+        }
+        #[cfg(debug_assertions)]
+        self.assert_invariants();
+    }
+
+    /// Returns the ranges `[start, end)` held in the buffers, ordered by start.
+    pub(crate) fn cached_ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.buffers.iter().map(|b| b.range())
+    }
+
+    /// Returns the start offset and data of each buffer, ordered by start. Corrupted buffers are
+    /// left out.
+    pub(crate) fn cached_chunks(&self) -> impl Iterator<Item = (u64, &[u8])> + '_ {
+        self.buffers
+            .iter()
+            .filter(|b| b.verify())
+            .map(|b| (b.range().0, b.data()))
+    }
+
+    /// Returns the total number of bytes held in the buffers.
+    pub(crate) fn cached_bytes(&self) -> u64 {
+        self.buffers
+            .iter()
+            .map(|b| {
+                let (start, end) = b.range();
+                end - start
+            })
+            .sum()
+    }
+
+    /// Returns the index of the buffer holding `offset`, or if there isn't one, the index a buffer
+    /// starting there would be inserted at.
+    pub(crate) fn buffer_index_for(&self, offset: u64) -> Result<usize, usize> {
+        // Only the last buffer starting at or before the offset can contain it
+        let i = self.buffers.partition_point(|b| b.range().0 <= offset);
+        match i.checked_sub(1) {
+            Some(prev) if self.buffers[prev].contains_offset(offset) => Ok(prev),
+            _ => Err(i),
+        }
+    }
+
+    /// Returns the data from `offset` to the end of the buffer holding it, if there is one.
+    pub(crate) fn get_prefix(&self, offset: u64) -> Option<&[u8]> {
+        self.intact_buffer_for(offset)?.get_available_from(offset)
+    }
+
+    /// Returns the data from `offset` onwards as [`Self::get_prefix`] does, or if `offset` is in a
+    /// hole, zeros up to where the hole ends. Long holes are handed out a piece at a time.
+    pub(crate) fn get_prefix_or_hole(&self, offset: u64) -> Option<&[u8]> {
+        if let Some(data) = self.get_prefix(offset) {
+            return Some(data);
+        }
+        let len = (self.hole_end(offset)? - offset).min(ZEROS.len() as u64);
+
+        Some(&ZEROS[..len as usize])
+    }
+
+    /// Returns `[offset, offset + length)` if it's held in a single buffer.
+    pub(crate) fn get_range(&self, offset: u64, length: u64) -> Option<&[u8]> {
+        self.intact_buffer_for(offset)?.get_range(offset, length)
+    }
+
+    /// Returns the buffer holding `offset`, unless its data has been corrupted. Everything which
+    /// hands out buffered data goes through here or checks for itself, so corrupted data is
+    /// treated as missing even where it can't be dropped yet.
+    fn intact_buffer_for(&self, offset: u64) -> Option<&Buffer<S>> {
+        let i = self.buffer_index_for(offset).ok()?;
+        Some(&self.buffers[i]).filter(|b| b.verify())
+    }
+
+    /// Returns the indices of the buffers holding any part of `[start, end)`.
+    fn overlapping(&self, start: u64, end: u64) -> std::ops::Range<usize> {
+        let first = self.buffers.partition_point(|b| b.range().1 <= start);
+        let last = self.buffers.partition_point(|b| b.range().0 < end);
+
+        first..last.max(first)
+    }
+
+    /// Copies out everything that's buffered from `offset` onwards, stopping at the first gap.
+    /// Holes are filled with zeros rather than counting as gaps. Returns the number of bytes
+    /// copied, along with the range `[start, end)` of the gap if the request couldn't be filled.
+    pub(crate) fn read_cached(&self, offset: u64, buf: &mut [u8]) -> (usize, Option<(u64, u64)>) {
+        let mut filled = 0;
+        for segment in self.covered_ranges(offset, buf.len() as u64) {
+            match segment {
+                Segment::Cached(_, data) => {
+                    buf[filled..filled + data.len()].copy_from_slice(data);
+                    filled += data.len();
+                }
+                Segment::Missing(mut start, end) => {
+                    while start < end {
+                        match self.next_hole(start) {
+                            Some((hole_start, hole_end)) if hole_start <= start => {
+                                let len = (hole_end.min(end) - start) as usize;
+                                buf[filled..filled + len].fill(0);
+                                filled += len;
+                                start += len as u64;
+                            }
+                            Some((hole_start, _)) => {
+                                return (filled, Some((start, end.min(hole_start))))
+                            }
+                            None => return (filled, Some((start, end))),
+                        }
+                    }
+                }
+            }
+        }
+
+        (filled, None)
+    }
+
+    /// Returns the gaps `[start, end)` within `[offset, offset + length)` which aren't held in
+    /// the buffers, in order. Holes don't count as gaps, as there's nothing to fetch.
+    pub(crate) fn missing_ranges(&self, offset: u64, length: u64) -> Vec<(u64, u64)> {
+        let missing = self
+            .covered_ranges(offset, length)
+            .into_iter()
+            .filter_map(|segment| match segment {
+                Segment::Missing(start, end) => Some((start, end)),
+                Segment::Cached(..) => None,
+            })
+            .collect();
+
+        self.holes.iter().fold(missing, |ranges, &hole| {
+            ranges
+                .into_iter()
+                .flat_map(|range| subtract(range, hole))
+                .collect()
+        })
+    }
+
+    /// Checks whether all of `[offset, offset + length)` is held in the buffers or known to be a
+    /// hole, even if it's spread across several of them.
+    pub(crate) fn is_cached(&self, offset: u64, length: u64) -> bool {
+        self.missing_ranges(offset, length).is_empty()
+    }
+
+    /// Returns the fraction of `[offset, offset + length)` which is held in the buffers or known
+    /// to be a hole. An empty range counts as fully covered.
+    pub(crate) fn coverage_fraction(&self, offset: u64, length: u64) -> f64 {
+        let length = offset.saturating_add(length) - offset;
+        if length == 0 {
+            return 1.0;
+        }
+
+        let missing = self
+            .missing_ranges(offset, length)
+            .iter()
+            .map(|(start, end)| end - start)
+            .sum::<u64>();
+
+        (length - missing) as f64 / length as f64
+    }
+
+    /// Returns the ranges `[start, end)` held by both caches where their data differs, in order.
+    /// Anything only held by one of them is left out.
+    pub(crate) fn diff<T: BufferStore>(&self, other: &Cache<T>) -> Vec<(u64, u64)> {
+        let mut diffs: Vec<(u64, u64)> = Vec::new();
+        for buffer in &self.buffers {
+            let (start, end) = buffer.range();
+            for segment in other.covered_ranges(start, end - start) {
+                let Segment::Cached(offset, theirs) = segment else {
+                    continue;
+                };
+                let ours = buffer
+                    .get_range(offset, theirs.len() as u64)
+                    .expect("segment lies within the buffer");
+
+                for (i, _) in ours
+                    .iter()
+                    .zip(theirs)
+                    .enumerate()
+                    .filter(|(_, (a, b))| a != b)
+                {
+                    // Runs of differing bytes are reported as one range
+                    let pos = offset + i as u64;
+                    match diffs.last_mut() {
+                        Some((_, diff_end)) if *diff_end == pos => *diff_end += 1,
+                        _ => diffs.push((pos, pos + 1)),
+                    }
+                }
+            }
+        }
+
+        diffs
+    }
+
+    /// Breaks up the range `[offset, offset + length)` into the pieces which are held in the
+    /// buffers and the gaps between them, in order. Pieces may span several buffers if they
+    /// haven't been merged together.
+    pub(crate) fn covered_ranges(&self, offset: u64, length: u64) -> Vec<Segment<'_>> {
+        let end = offset.saturating_add(length);
+
+        let mut segments = Vec::new();
+        let mut pos = offset;
+        while pos < end {
+            // Take as much as we can from the buffer containing this position
+            if let Some(data) = self.get_prefix(pos) {
+                let data = &data[..data.len().min((end - pos) as usize)];
+                segments.push(Segment::Cached(pos, data));
+                pos += data.len() as u64;
+                continue;
+            }
+
+            // Otherwise we have a gap up until the next buffer starts
+            let next = self.buffers.partition_point(|b| b.range().0 <= pos);
+            let gap_end = self.buffers.get(next).map_or(end, |b| b.range().0.min(end));
+            segments.push(Segment::Missing(pos, gap_end));
+            pos = gap_end;
+        }
+
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, MergePolicy, Segment};
+    use crate::buffer::Buffer;
+
+    #[test]
+    fn test_covered_ranges() {
+        let mut cache = Cache::default();
+        cache.buffers.push(Buffer::from_slice(10, &[1; 10]));
+        cache.buffers.push(Buffer::from_slice(20, &[2; 10]));
+        cache.buffers.push(Buffer::from_slice(30, &[3; 10]));
+
+        assert_eq!(
+            cache.covered_ranges(0, 50),
+            vec![
+                Segment::Missing(0, 10),
+                Segment::Cached(10, &[1; 10]),
+                Segment::Cached(20, &[2; 10]),
+                Segment::Cached(30, &[3; 10]),
+                Segment::Missing(40, 50),
+            ]
+        );
+        assert_eq!(
+            cache.covered_ranges(15, 10),
+            vec![Segment::Cached(15, &[1; 5]), Segment::Cached(20, &[2; 5])]
+        );
+        assert_eq!(cache.covered_ranges(50, 10), vec![Segment::Missing(50, 60)]);
+        assert_eq!(cache.covered_ranges(50, 0), vec![]);
+    }
+
+    #[test]
+    fn test_merge_never() {
+        let mut cache = Cache {
+            merge_policy: MergePolicy::Never,
+            ..Cache::default()
+        };
+        cache.add_buffer(10, &[1; 10]);
+        cache.add_buffer(30, &[2; 10]);
+        // Overlapping both, so only the parts either side and the middle are new
+        cache.add_buffer(5, &[3; 40]);
+        assert_eq!(
+            cache.cached_ranges().collect::<Vec<_>>(),
+            vec![(5, 10), (10, 20), (20, 30), (30, 40), (40, 45)]
+        );
+
+        let mut buf = [0; 40];
+        assert_eq!(cache.read_cached(5, &mut buf), (40, None));
+        assert_eq!(&buf[..5], &[3; 5]);
+        assert_eq!(&buf[5..15], &[1; 10]);
+        assert_eq!(&buf[15..25], &[3; 10]);
+        assert_eq!(&buf[25..35], &[2; 10]);
+
+        // Fully covered already, so nothing changes
+        cache.add_buffer(12, &[4; 4]);
+        assert_eq!(cache.buffers.len(), 5);
+        assert_eq!(cache.gaps_around(12), vec![]);
+    }
+
+    #[test]
+    fn test_read_cached() {
+        let mut cache = Cache::default();
+        cache.add_buffer(10, &[1; 10]);
+        cache.add_buffer(30, &[2; 10]);
+
+        let mut buf = [0; 15];
+        assert_eq!(cache.read_cached(15, &mut buf), (5, Some((20, 30))));
+        assert_eq!(&buf[..5], &[1; 5]);
+
+        let mut buf = [0; 5];
+        assert_eq!(cache.read_cached(30, &mut buf), (5, None));
+        assert_eq!(buf, [2; 5]);
+
+        assert_eq!(cache.read_cached(0, &mut buf), (0, Some((0, 5))));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_assert_invariants() {
+        // Checked along the way by each of these
+        let mut cache = Cache::default();
+        cache.add_buffer(20, &[2; 10]);
+        cache.add_buffer(0, &[1; 10]);
+        cache.add_buffer(5, &[3; 10]);
+        cache.invalidate(12, 22);
+        cache.add_buffer(30, &[4; 10]);
+        cache.coalesce_adjacent();
+        assert_eq!(
+            cache.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 12), (22, 40)]
+        );
+        cache.assert_invariants();
+
+        // Touching buffers are fine
+        cache.buffers.insert(1, Buffer::from_slice(12, &[0; 10]));
+        cache.assert_invariants();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is followed by one starting at")]
+    fn test_assert_invariants_overlapping() {
+        let mut cache = Cache::default();
+        cache.buffers.push(Buffer::from_slice(0, &[1; 10]));
+        cache.buffers.push(Buffer::from_slice(5, &[1; 10]));
+        cache.assert_invariants();
+    }
+
+    #[test]
+    fn test_buffer_index_for() {
+        let mut cache = Cache::default();
+        cache.add_buffer(10, &[1; 10]);
+        cache.add_buffer(30, &[2; 10]);
+
+        assert_eq!(cache.buffer_index_for(0), Err(0));
+        assert_eq!(cache.buffer_index_for(10), Ok(0));
+        assert_eq!(cache.buffer_index_for(19), Ok(0));
+        assert_eq!(cache.buffer_index_for(20), Err(1));
+        assert_eq!(cache.buffer_index_for(30), Ok(1));
+        assert_eq!(cache.buffer_index_for(40), Err(2));
+    }
+
+    #[test]
+    fn test_add_empty_buffer() {
+        let mut cache = Cache::default();
+        cache.add_buffer(10, &[]);
+        assert!(cache.buffers.is_empty());
+
+        cache.add_buffer(0, &[1; 10]);
+        cache.add_buffer(10, &[]);
+        cache.add_buffer(5, &[]);
+        assert_eq!(cache.cached_ranges().collect::<Vec<_>>(), vec![(0, 10)]);
+    }
+
+    #[test]
+    fn test_missing_ranges() {
+        let mut cache = Cache::default();
+        assert_eq!(cache.missing_ranges(10, 20), vec![(10, 30)]);
+
+        cache.add_buffer(10, &[1; 10]);
+        cache.add_buffer(25, &[2; 10]);
+        assert_eq!(cache.missing_ranges(12, 5), vec![]);
+        assert_eq!(cache.missing_ranges(10, 25), vec![(20, 25)]);
+        assert_eq!(
+            cache.missing_ranges(0, 40),
+            vec![(0, 10), (20, 25), (35, 40)]
+        );
+        assert_eq!(cache.missing_ranges(10, 0), vec![]);
+
+        // Holes don't need fetching
+        cache.mark_hole(0, 5);
+        cache.mark_hole(22, 23);
+        assert_eq!(
+            cache.missing_ranges(0, 40),
+            vec![(5, 10), (20, 22), (23, 25), (35, 40)]
+        );
+    }
+
+    #[test]
+    fn test_is_cached() {
+        let mut cache = Cache::default();
+        cache.buffers.push(Buffer::from_slice(10, &[1; 10]));
+        cache.buffers.push(Buffer::from_slice(20, &[2; 10]));
+        cache.buffers.push(Buffer::from_slice(40, &[3; 10]));
+
+        // Across two adjacent buffers
+        assert!(cache.is_cached(15, 10));
+        assert!(cache.is_cached(10, 20));
+        // Partially cached
+        assert!(!cache.is_cached(5, 10));
+        assert!(!cache.is_cached(25, 20));
+        // Not cached at all
+        assert!(!cache.is_cached(30, 10));
+        assert!(!cache.is_cached(100, 1));
+    }
+
+    #[test]
+    fn test_coverage_fraction() {
+        let mut cache = Cache::default();
+        cache.add_buffer(10, &[1; 10]);
+        cache.add_buffer(30, &[2; 10]);
+
+        assert_eq!(cache.coverage_fraction(50, 10), 0.0);
+        assert_eq!(cache.coverage_fraction(10, 10), 1.0);
+        assert_eq!(cache.coverage_fraction(12, 5), 1.0);
+        assert_eq!(cache.coverage_fraction(10, 20), 0.5);
+        assert_eq!(cache.coverage_fraction(0, 40), 0.5);
+        assert_eq!(cache.coverage_fraction(100, 0), 1.0);
+    }
+
+    #[test]
+    fn test_many_disjoint_buffers() {
+        let mut cache = Cache::default();
+
+        // Insert out of order, with gaps in between so nothing merges
+        for i in (0..5000u64).rev().step_by(2).chain((0..5000).step_by(2)) {
+            cache.add_buffer(i * 10, &[i as u8; 5]);
+        }
+        assert_eq!(cache.buffers.len(), 5000);
+        assert!(cache
+            .buffers
+            .windows(2)
+            .all(|w| w[0].range().1 < w[1].range().0));
+
+        for i in 0..5000u64 {
+            assert_eq!(cache.get_prefix(i * 10), Some([i as u8; 5].as_slice()));
+            assert_eq!(cache.get_prefix(i * 10 + 3), Some([i as u8; 2].as_slice()));
+            assert_eq!(cache.get_prefix(i * 10 + 5), None);
+        }
+
+        // Filling a gap merges with the neighbours on both sides
+        cache.add_buffer(15, &[0; 5]);
+        assert_eq!(cache.buffers.len(), 4999);
+        assert_eq!(cache.buffers[1].range(), (10, 25));
+        assert_eq!(
+            cache.covered_ranges(5, 30),
+            vec![
+                Segment::Missing(5, 10),
+                Segment::Cached(10, &[1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 2, 2, 2, 2, 2]),
+                Segment::Missing(25, 30),
+                Segment::Cached(30, &[3; 5]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{error::SaturatingError, saturating_reader::SaturatingReader};
+
+/// Adapts a decompressing reader, such as a gzip decoder, into a [`Read`] + [`Seek`] reader over
+/// the decompressed stream, so it can sit underneath a [`SaturatingReader`]. The reader above
+/// then caches decompressed data by its decompressed offset.
+///
+/// Decompressors can't jump around, so seeking forwards decompresses and throws away everything
+/// in between, and seeking backwards starts again from the beginning with a new decompressor from
+/// `open`. There are no checkpoints to pick up from part way, as a decompressor's state can't
+/// generally be saved, so the reader above should be left to cache anything that's read more
+/// than once. Seeking from the end isn't supported, as the decompressed length isn't known, but
+/// it can be given to the reader above with [`SaturatingReader::set_stream_len_hint`]. Seeking
+/// past the end is allowed, and reads from there find nothing.
+#[derive(Debug)]
+pub struct DecompressingSource<D: Read, F: FnMut() -> std::io::Result<D>> {
+    open: F,
+    decoder: D,
+    // Offset into the decompressed stream
+    pos: u64,
+    opened: usize,
+}
+
+impl<D: Read, F: FnMut() -> std::io::Result<D>> DecompressingSource<D, F> {
+    /// Creates a source which calls `open` for a decompressor positioned at the start of the
+    /// stream, both now and on any backward seek.
+    pub fn new(mut open: F) -> std::io::Result<Self> {
+        let decoder = open()?;
+
+        Ok(Self {
+            open,
+            decoder,
+            pos: 0,
+            opened: 1,
+        })
+    }
+
+    /// Returns how many decompressors have been opened, including the first.
+    pub fn opened(&self) -> usize {
+        self.opened
+    }
+}
+
+impl<D: Read, F: FnMut() -> std::io::Result<D>> Read for DecompressingSource<D, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let num_bytes_read = self.decoder.read(buf)?;
+        self.pos += num_bytes_read as u64;
+
+        Ok(num_bytes_read)
+    }
+}
+
+impl<D: Read, F: FnMut() -> std::io::Result<D>> Seek for DecompressingSource<D, F> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(p) => p,
+            SeekFrom::Current(p) => {
+                self.pos
+                    .checked_add_signed(p)
+                    .ok_or(SaturatingError::SeekUnderflow {
+                        position: self.pos,
+                        offset: p,
+                    })?
+            }
+            SeekFrom::End(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "Decompressing sources can't seek from the end.",
+                ))
+            }
+        };
+
+        // Going backwards means starting again
+        if target < self.pos {
+            self.decoder = (self.open)()?;
+            self.opened += 1;
+            self.pos = 0;
+        }
+
+        // Then decompress up to the target, which may run into the end of the stream. Past the end
+        // the decoder has nothing more to give, so we're where we were asked to be regardless.
+        std::io::copy(
+            &mut (&mut self.decoder).take(target - self.pos),
+            &mut std::io::sink(),
+        )?;
+        self.pos = target;
+
+        Ok(target)
+    }
+}
+
+impl<D: Read, F: FnMut() -> std::io::Result<D>> SaturatingReader<DecompressingSource<D, F>> {
+    /// Creates a reader which caches the decompressed output of decompressors from `open`, as
+    /// described on [`DecompressingSource`].
+    pub fn from_decompressor(open: F) -> std::io::Result<Self> {
+        Ok(Self::new(DecompressingSource::new(open)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+    use super::DecompressingSource;
+    use crate::saturating_reader::SaturatingReader;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompressing_source() {
+        let data = (0..10_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let blob = gzip(&data);
+        let mut source = DecompressingSource::new(|| Ok(GzDecoder::new(blob.as_slice()))).unwrap();
+
+        // Forwards then backwards
+        let mut buf = [0; 10];
+        assert_eq!(source.seek(SeekFrom::Start(5000)).unwrap(), 5000);
+        source.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), &data[5000..5010]);
+        assert_eq!(source.opened(), 1);
+        assert_eq!(source.seek(SeekFrom::Current(-1010)).unwrap(), 4000);
+        source.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), &data[4000..4010]);
+        assert_eq!(source.opened(), 2);
+
+        assert!(source.seek(SeekFrom::End(0)).is_err());
+        assert!(source.seek(SeekFrom::Current(-5000)).is_err());
+
+        // Past the end, relative seeks carry on from where we said we were
+        assert_eq!(source.seek(SeekFrom::Start(20_000)).unwrap(), 20_000);
+        assert_eq!(source.seek(SeekFrom::Current(-10)).unwrap(), 19_990);
+        assert_eq!(source.read(&mut buf).unwrap(), 0);
+        assert_eq!(source.seek(SeekFrom::Current(-15_000)).unwrap(), 4990);
+        source.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), &data[4990..5000]);
+    }
+
+    #[test]
+    fn test_from_decompressor() {
+        let data = (0..10_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let blob = gzip(&data);
+        let mut bufreader =
+            SaturatingReader::from_decompressor(|| Ok(GzDecoder::new(blob.as_slice()))).unwrap();
+
+        // Reading the same region twice only decompresses it once
+        let mut buf = [0; 100];
+        for _ in 0..2 {
+            bufreader.seek(SeekFrom::Start(6000)).unwrap();
+            bufreader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), &data[6000..6100]);
+        }
+        assert_eq!(bufreader.stats().misses, 1);
+        assert_eq!(bufreader.get_ref().opened(), 1);
+
+        // An earlier region has to start again from the beginning
+        bufreader.seek(SeekFrom::Start(100)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), &data[100..200]);
+        assert_eq!(bufreader.into_inner().opened(), 2);
+    }
+}
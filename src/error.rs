@@ -0,0 +1,52 @@
+use std::fmt;
+
+/// Errors raised by the readers in this crate, as opposed to those passed up from an inner
+/// reader. They're returned wrapped in an [`std::io::Error`], and can be recovered from it with
+/// [`std::io::Error::get_ref`] and a downcast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SaturatingError {
+    /// A relative seek would have moved the cursor before the start of the stream.
+    SeekUnderflow { position: u64, offset: i64 },
+    /// Reading any more from the inner reader would go over the read quota.
+    ReadQuotaExceeded { quota: u64 },
+    /// A fetch from the inner reader was abandoned because the cancel flag was set.
+    Cancelled,
+    /// Loading `len` bytes in one go would go over the memory limit of `limit` bytes.
+    TooLargeToCache { len: u64, limit: u64 },
+}
+
+impl fmt::Display for SaturatingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SeekUnderflow { position, offset } => write!(
+                f,
+                "Seek position underflowed: can't seek {offset} bytes from position {position}."
+            ),
+            Self::ReadQuotaExceeded { quota } => write!(
+                f,
+                "Read quota exceeded: can't read more than {quota} bytes from the inner reader."
+            ),
+            Self::Cancelled => write!(f, "Fetch from the inner reader was cancelled."),
+            Self::TooLargeToCache { len, limit } => write!(
+                f,
+                "Too large to cache: {len} bytes won't fit within the limit of {limit} bytes."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaturatingError {}
+
+impl From<SaturatingError> for std::io::Error {
+    fn from(e: SaturatingError) -> Self {
+        let kind = match e {
+            SaturatingError::SeekUnderflow { .. } => std::io::ErrorKind::InvalidInput,
+            SaturatingError::ReadQuotaExceeded { .. } => std::io::ErrorKind::QuotaExceeded,
+            SaturatingError::Cancelled => std::io::ErrorKind::Interrupted,
+            SaturatingError::TooLargeToCache { .. } => std::io::ErrorKind::OutOfMemory,
+        };
+
+        std::io::Error::new(kind, e)
+    }
+}
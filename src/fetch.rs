@@ -0,0 +1,151 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{error::SaturatingError, saturating_reader::SaturatingReader};
+
+/// A source which can be asked for arbitrary ranges of bytes, such as an HTTP server supporting
+/// `Range:` requests or an object store.
+pub trait RangeFetch {
+    /// Fetches up to `len` bytes starting at `offset`. Fewer bytes may be returned, and nothing at
+    /// all means the source has no data at that offset.
+    fn fetch(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>>;
+}
+
+impl<T: Read + Seek> RangeFetch for T {
+    fn fetch(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        self.seek(SeekFrom::Start(offset))?;
+
+        let mut buf = vec![0; len];
+        let num_bytes_read = self.read(&mut buf)?;
+        buf.truncate(num_bytes_read);
+
+        Ok(buf)
+    }
+}
+
+/// Adapts a [`RangeFetch`] source into a [`Read`] + [`Seek`] reader, so it can sit underneath a
+/// [`SaturatingReader`]. Each read is a single fetch at the current position.
+///
+/// The length of the source isn't known, so seeking from the end isn't supported.
+#[derive(Debug, Clone)]
+pub struct FetchReader<F: RangeFetch> {
+    inner: F,
+    pos: u64,
+}
+
+impl<F: RangeFetch> FetchReader<F> {
+    pub fn new(inner: F) -> Self {
+        Self { inner, pos: 0 }
+    }
+
+    /// Consumes the reader, returning the underlying source.
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+impl<F: RangeFetch> Read for FetchReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.inner.fetch(self.pos, buf.len())?;
+        // Don't trust the source to stick to what we asked for
+        let num_bytes_read = data.len().min(buf.len());
+        buf[..num_bytes_read].copy_from_slice(&data[..num_bytes_read]);
+        self.pos += num_bytes_read as u64;
+
+        Ok(num_bytes_read)
+    }
+}
+
+impl<F: RangeFetch> Seek for FetchReader<F> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(p) => p,
+            SeekFrom::Current(p) => {
+                self.pos
+                    .checked_add_signed(p)
+                    .ok_or(SaturatingError::SeekUnderflow {
+                        position: self.pos,
+                        offset: p,
+                    })?
+            }
+            SeekFrom::End(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "Range fetch sources can't seek from the end.",
+                ))
+            }
+        };
+
+        Ok(self.pos)
+    }
+}
+
+impl<F: RangeFetch> SaturatingReader<FetchReader<F>> {
+    /// Creates a reader which fetches ranges from the given source as they're needed, keeping
+    /// everything fetched in its internal buffers.
+    pub fn from_fetch(inner: F) -> Self {
+        Self::new(FetchReader::new(inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use super::{FetchReader, RangeFetch};
+    use crate::saturating_reader::SaturatingReader;
+
+    /// Serves a fixed slice, counting how many fetches were made.
+    struct CountingFetch {
+        data: Vec<u8>,
+        fetches: usize,
+    }
+
+    impl RangeFetch for CountingFetch {
+        fn fetch(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+            self.fetches += 1;
+            let start = (offset as usize).min(self.data.len());
+            let end = start.saturating_add(len).min(self.data.len());
+            Ok(self.data[start..end].to_vec())
+        }
+    }
+
+    #[test]
+    fn test_read_seek_fetch() {
+        let mut reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        assert_eq!(reader.fetch(10, 5).unwrap(), vec![10, 11, 12, 13, 14]);
+        assert_eq!(
+            reader.fetch(250, 10).unwrap(),
+            vec![250, 251, 252, 253, 254, 255]
+        );
+        assert!(reader.fetch(300, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_from_fetch() {
+        let fetch = CountingFetch {
+            data: (0..=255).collect(),
+            fetches: 0,
+        };
+        let mut bufreader = SaturatingReader::from_fetch(fetch);
+
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, (0..=255).collect::<Vec<_>>());
+
+        // Everything is served from the cache the second time round
+        let fetches = bufreader.stats().misses;
+        bufreader.seek(SeekFrom::Start(100)).unwrap();
+        let mut buf = [0; 50];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (100..150).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.stats().misses, fetches);
+        assert_eq!(
+            bufreader.into_inner().into_inner().fetches,
+            fetches as usize
+        );
+
+        assert!(FetchReader::new(Cursor::new(vec![0; 10]))
+            .seek(SeekFrom::End(0))
+            .is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use std::{fs::File, time::SystemTime};
+
+/// Identifies a particular version of a source's contents, so changes to it can be noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub len: u64,
+    /// When the source was last modified, if the platform keeps track.
+    pub modified: Option<SystemTime>,
+}
+
+/// A source which can describe its current contents cheaply, without reading them.
+pub trait SourceFingerprint {
+    fn fingerprint(&self) -> std::io::Result<Fingerprint>;
+}
+
+impl SourceFingerprint for File {
+    fn fingerprint(&self) -> std::io::Result<Fingerprint> {
+        let metadata = self.metadata()?;
+
+        Ok(Fingerprint {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Seek, SeekFrom, Write},
+    };
+
+    use crate::saturating_reader::SaturatingReader;
+
+    #[test]
+    fn test_refresh_if_changed() {
+        let path = std::env::temp_dir().join(format!("saturating_reader_{}", std::process::id()));
+        std::fs::write(&path, (0..=255).collect::<Vec<_>>()).unwrap();
+
+        let mut bufreader = SaturatingReader::with_fingerprint(File::open(&path).unwrap()).unwrap();
+        let mut buf = [0; 100];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert!(!bufreader.refresh_if_changed().unwrap());
+        assert_eq!(bufreader.cached_bytes(), 256);
+
+        // Rewrite the file out from under the reader
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&[1; 100]).unwrap();
+        drop(file);
+
+        assert!(bufreader.refresh_if_changed().unwrap());
+        assert_eq!(bufreader.cached_bytes(), 0);
+        bufreader.seek(SeekFrom::Start(0)).unwrap();
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, vec![1; 100]);
+        assert_eq!(bufreader.seek(SeekFrom::End(0)).unwrap(), 100);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
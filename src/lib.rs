@@ -1,3 +1,19 @@
-mod buffer;
-pub mod saturating_reader;
+#![cfg_attr(
+    feature = "nightly",
+    feature(read_buf, core_io_borrowed_buf, borrowed_buf_init)
+)]
 
+#[cfg(feature = "tokio")]
+pub mod async_reader;
+pub mod buffer;
+mod cache;
+pub mod decompress;
+pub mod error;
+pub mod fetch;
+pub mod fingerprint;
+mod persist;
+pub mod read_writer;
+pub mod saturating_reader;
+pub mod shared;
+pub mod stats;
+pub mod view;
@@ -0,0 +1,170 @@
+//! A simple binary format for saving the contents of the cache between runs.
+//!
+//! The layout is, with all integers little endian:
+//! - 8 byte magic, `SATCACHE`
+//! - `u32` format version
+//! - `u64` number of buffers
+//! - for each buffer, its `u64` start offset, `u64` length, then the data itself
+//!
+//! There's also a more compact format for just the ranges which are buffered, without the data.
+//! Each number is an unsigned LEB128 varint, least significant 7 bits first with the top bit of
+//! each byte set if more follow:
+//! - the number of ranges
+//! - for each range in order, the gap since the end of the previous range (or since 0 for the
+//!   first), then its length. Ranges never overlap or touch, and are never empty.
+
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use crate::buffer::{Buffer, BufferStore};
+
+const MAGIC: &[u8; 8] = b"SATCACHE";
+const VERSION: u32 = 1;
+
+/// Writes out the given buffers.
+pub(crate) fn write_buffers<'a, W: Write, S: BufferStore + 'a>(
+    w: &mut W,
+    buffers: impl ExactSizeIterator<Item = &'a Buffer<S>>,
+) -> Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&(buffers.len() as u64).to_le_bytes())?;
+
+    for buffer in buffers {
+        let (start, end) = buffer.range();
+        w.write_all(&start.to_le_bytes())?;
+        w.write_all(&(end - start).to_le_bytes())?;
+        w.write_all(buffer.data())?;
+    }
+
+    Ok(())
+}
+
+/// Reads back buffers written by [`write_buffers`], rejecting anything that isn't in the same
+/// format.
+pub(crate) fn read_buffers<R: Read>(r: &mut R) -> Result<Vec<Buffer>> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Not a saved cache."));
+    }
+
+    let version = u32::from_le_bytes(read_array(r)?);
+    if version != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported cache format version {version}."),
+        ));
+    }
+
+    let count = u64::from_le_bytes(read_array(r)?);
+    let mut buffers = Vec::new();
+    for _ in 0..count {
+        let start = u64::from_le_bytes(read_array(r)?);
+        let length = u64::from_le_bytes(read_array(r)?);
+        if start.checked_add(length).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Buffer range overflows.",
+            ));
+        }
+
+        // Don't trust the length for the allocation, in case the data is truncated or corrupt
+        let mut data = Vec::new();
+        r.take(length).read_to_end(&mut data)?;
+        if data.len() as u64 != length {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Buffer data is truncated.",
+            ));
+        }
+
+        buffers.push(Buffer::from_slice(start, &data));
+    }
+
+    Ok(buffers)
+}
+
+/// Writes out the given ranges, which must be sorted and disjoint. Touching ranges are combined.
+pub(crate) fn write_coverage<W: Write>(
+    w: &mut W,
+    ranges: impl Iterator<Item = (u64, u64)>,
+) -> Result<()> {
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if last.1 == start => last.1 = end,
+            _ => merged.push((start, end)),
+        }
+    }
+
+    write_varint(w, merged.len() as u64)?;
+    let mut prev_end = 0;
+    for (start, end) in merged {
+        write_varint(w, start - prev_end)?;
+        write_varint(w, end - start)?;
+        prev_end = end;
+    }
+
+    Ok(())
+}
+
+/// Reads back the ranges written by
+/// [`SaturatingReader::export_coverage`](crate::saturating_reader::SaturatingReader::export_coverage),
+/// such as to compare what two readers have buffered.
+pub fn import_coverage<R: Read>(r: &mut R) -> Result<Vec<(u64, u64)>> {
+    let count = read_varint(r)?;
+    let mut ranges = Vec::new();
+    let mut prev_end = 0u64;
+    for _ in 0..count {
+        let start = prev_end.checked_add(read_varint(r)?);
+        let length = read_varint(r)?;
+        let Some((start, end)) = start.and_then(|start| Some((start, start.checked_add(length)?)))
+        else {
+            return Err(Error::new(ErrorKind::InvalidData, "Range overflows."));
+        };
+        if length == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Range is empty."));
+        }
+
+        ranges.push((start, end));
+        prev_end = end;
+    }
+
+    Ok(ranges)
+}
+
+fn write_varint<W: Write>(w: &mut W, mut value: u64) -> Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(r: &mut R) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let [byte] = read_array(r)?;
+        let bits = u64::from(byte & 0x7f);
+        // The last byte of a u64 only has room for one more bit
+        if bits << shift >> shift != bits {
+            break;
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(Error::new(ErrorKind::InvalidData, "Varint overflows."))
+}
+
+fn read_array<R: Read, const N: usize>(r: &mut R) -> Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+
+    Ok(buf)
+}
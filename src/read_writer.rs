@@ -0,0 +1,180 @@
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+
+use crate::saturating_reader::SaturatingReader;
+
+/// A [`SaturatingReader`] over something which can also be written to. Writes go straight
+/// through to the inner reader, and any buffered data they cover is updated to match, so later
+/// reads never see stale bytes.
+///
+/// Written data which wasn't buffered beforehand isn't added to the buffers, and will be fetched
+/// on the next read as usual.
+#[derive(Debug, Clone)]
+pub struct SaturatingReadWriter<R: Read + Write + Seek> {
+    reader: SaturatingReader<R>,
+}
+
+impl<R: Read + Write + Seek> SaturatingReadWriter<R> {
+    pub fn new(inner: R) -> Self {
+        Self::from(SaturatingReader::new(inner))
+    }
+
+    /// Writes all of `data` at the given offset without moving the cursor.
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> std::io::Result<()> {
+        self.reader.write_at(offset, data)
+    }
+
+    /// Returns the reader underneath, for anything that isn't covered by the methods here.
+    pub fn reader(&self) -> &SaturatingReader<R> {
+        &self.reader
+    }
+
+    /// Consumes the read-writer, returning the reader underneath.
+    pub fn into_reader(self) -> SaturatingReader<R> {
+        self.reader
+    }
+}
+
+impl<R: Read + Write + Seek> From<SaturatingReader<R>> for SaturatingReadWriter<R> {
+    fn from(reader: SaturatingReader<R>) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read + Write + Seek> Read for SaturatingReadWriter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: Read + Write + Seek> BufRead for SaturatingReadWriter<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt);
+    }
+}
+
+impl<R: Read + Write + Seek> Write for SaturatingReadWriter<R> {
+    /// Writes at the cursor, moving it past what was written.
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let pos = self.reader.position();
+        self.write_at(pos, buf)?;
+        self.reader.set_position(pos + buf.len() as u64);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.reader.get_mut().flush()
+    }
+}
+
+impl<R: Read + Write + Seek> Seek for SaturatingReadWriter<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.reader.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+
+    use super::SaturatingReadWriter;
+
+    /// Takes at most `limit` bytes of writes, then fails.
+    struct ShortWriter {
+        inner: Cursor<Vec<u8>>,
+        limit: usize,
+    }
+
+    impl Read for ShortWriter {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.limit == 0 {
+                return Err(ErrorKind::StorageFull.into());
+            }
+            let len = buf.len().min(self.limit);
+            self.limit -= len;
+            self.inner.write(&buf[..len])
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Seek for ShortWriter {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_write_through() {
+        let mut rw = SaturatingReadWriter::new(Cursor::new((0..100).collect::<Vec<_>>()));
+        let mut buf = [0; 100];
+        rw.read_exact(&mut buf).unwrap();
+        let misses = rw.reader().stats().misses;
+
+        // Overwrite the middle of what's buffered, then read it back from the cache
+        rw.write_at(40, &[255; 20]).unwrap();
+        rw.seek(SeekFrom::Start(30)).unwrap();
+        let mut buf = [0; 40];
+        rw.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..10], (30..40).collect::<Vec<_>>().as_slice());
+        assert_eq!(&buf[10..30], &[255; 20]);
+        assert_eq!(&buf[30..], (60..70).collect::<Vec<_>>().as_slice());
+        assert_eq!(rw.reader().stats().misses, misses);
+
+        // The inner writer got it too
+        let inner = rw.into_reader().into_inner().into_inner();
+        assert_eq!(&inner[40..60], &[255; 20]);
+    }
+
+    #[test]
+    fn test_write_past_end() {
+        let mut rw = SaturatingReadWriter::new(Cursor::new((0..100).collect::<Vec<_>>()));
+        assert_eq!(rw.seek(SeekFrom::End(-10)).unwrap(), 90);
+
+        // Writing from the cursor moves it along, and extends the stream
+        rw.write_all(&[1; 20]).unwrap();
+        assert_eq!(rw.stream_position().unwrap(), 110);
+        assert_eq!(rw.seek(SeekFrom::End(0)).unwrap(), 110);
+
+        rw.seek(SeekFrom::Start(80)).unwrap();
+        let mut buf = vec![];
+        rw.read_to_end(&mut buf).unwrap();
+        assert_eq!(&buf[..10], (80..90).collect::<Vec<_>>().as_slice());
+        assert_eq!(&buf[10..], &[1; 20]);
+    }
+
+    #[test]
+    fn test_failed_write() {
+        let mut rw = SaturatingReadWriter::new(ShortWriter {
+            inner: Cursor::new((0..100).collect()),
+            limit: 5,
+        });
+        let mut buf = [0; 100];
+        rw.read_exact(&mut buf).unwrap();
+
+        // Only part of this makes it through, so the buffers are dropped over all of it
+        assert!(rw.write_at(40, &[255; 20]).is_err());
+        assert_eq!(
+            rw.reader().cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 40), (60, 100)]
+        );
+
+        // Reading it back matches what the inner reader actually holds
+        rw.seek(SeekFrom::Start(38)).unwrap();
+        let mut buf = [0; 10];
+        rw.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [38, 39, 255, 255, 255, 255, 255, 45, 46, 47]);
+    }
+}
@@ -1,14 +1,260 @@
-use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::{
+    borrow::Cow,
+    io::{BufRead, IoSliceMut, Read, Seek, SeekFrom, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use crate::buffer::Buffer;
+use crate::{
+    buffer::{Buffer, BufferStore, HeapStore},
+    cache::Cache,
+    error::SaturatingError,
+    fingerprint::{Fingerprint, SourceFingerprint},
+    persist,
+    stats::{CacheEvent, CacheStats, DropHook, TrackedStats},
+    view::CacheView,
+};
+pub use crate::{
+    cache::{MergePolicy, Segment},
+    persist::import_coverage,
+};
+
+/// The default cap on how much is read from the inner reader in one go.
+pub const DEFAULT_MAX_READ_SIZE: usize = 64 * 1024 * 1024;
+
+/// How failed reads from the inner reader are retried. Only errors which are likely to be
+/// transient are retried: [`std::io::ErrorKind::Interrupted`], [`std::io::ErrorKind::TimedOut`]
+/// and [`std::io::ErrorKind::WouldBlock`]. Anything else, or an error after the last retry, is
+/// passed straight up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RetryPolicy {
+    /// Most times a single read is retried.
+    pub max_retries: u32,
+    /// How long to wait before the first retry. The wait doubles with each retry after that.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// How long to wait before the given retry, counting from zero.
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(retry))
+    }
+
+    fn is_retryable(e: &std::io::Error) -> bool {
+        matches!(
+            e.kind(),
+            std::io::ErrorKind::Interrupted
+                | std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::WouldBlock
+        )
+    }
+}
 
 /// A reader which maintains internal buffers of everything it reads.
-#[derive(Debug)]
-pub struct SaturatingReader<R: Read + Seek> {
+///
+/// Cloning the reader deep copies the internal buffers, so the caches of the original and the
+/// clone evolve independently afterwards.
+#[derive(Debug, Clone)]
+pub struct SaturatingReader<R: Read + Seek, S: BufferStore = HeapStore> {
     inner: R,
-    buffers: Vec<Buffer>,
+    cache: Cache<S>,
     cursor_pos: u64,
     bufread_size: usize,
+    // Most we'll read from the inner reader in one go, however much is asked for
+    max_read_size: usize,
+    // How much we're currently reading ahead on a miss, which grows during sequential access up
+    // to the max
+    readahead: usize,
+    max_readahead: usize,
+    stats: TrackedStats,
+    // Length of the inner reader, once we've had reason to find it out. The inner reader may
+    // have grown since, so this doesn't stop reads.
+    stream_len: Option<u64>,
+    // Length we've been told the inner reader is, which unlike the one we found does stop reads
+    stream_len_hint: Option<u64>,
+    // Where the inner reader is positioned, if we know for sure
+    inner_pos: Option<u64>,
+    // How far either side of the cursor buffered data is kept, if limited
+    window: Option<u64>,
+    // What the inner reader looked like when the buffered data was read, if we're watching it
+    fingerprint: Option<Fingerprint>,
+    // Most we'll ever read from the inner reader, if limited
+    read_quota: Option<u64>,
+    // Everything read from the inner reader so far, and the most ever buffered at once, which
+    // unlike the stats are never reset
+    inner_bytes_read: u64,
+    peak_cached_bytes: u64,
+    // Whether to keep reading from the inner reader until a fetch is filled
+    coalesce_partial_reads: bool,
+    // Where reads stop, regardless of how much more the inner reader has
+    logical_eof: Option<u64>,
+    // Once set, fetches from the inner reader fail rather than going ahead
+    cancel: Option<Arc<AtomicBool>>,
+    retry_policy: RetryPolicy,
+}
+
+/// Configures a [`SaturatingReader`] one setting at a time. Anything left unset behaves as it does
+/// for [`SaturatingReader::new`].
+#[derive(Debug, Clone)]
+pub struct SaturatingReaderBuilder {
+    read_size: usize,
+    max_read_size: usize,
+    max_readahead: Option<usize>,
+    max_cached_bytes: Option<usize>,
+    max_buffers: Option<usize>,
+    window: Option<usize>,
+    read_quota: Option<u64>,
+    merge_policy: MergePolicy,
+    coalesce_partial_reads: bool,
+    retry_policy: RetryPolicy,
+    on_drop: DropHook,
+}
+
+impl Default for SaturatingReaderBuilder {
+    fn default() -> Self {
+        Self {
+            read_size: 8 * 1024,
+            max_read_size: DEFAULT_MAX_READ_SIZE,
+            max_readahead: None,
+            max_cached_bytes: None,
+            max_buffers: None,
+            window: None,
+            read_quota: None,
+            merge_policy: MergePolicy::Always,
+            coalesce_partial_reads: false,
+            retry_policy: RetryPolicy::default(),
+            on_drop: DropHook::default(),
+        }
+    }
+}
+
+impl SaturatingReaderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the minimum amount fetched from the inner reader on a miss.
+    ///
+    /// # Panics
+    /// When building, if `read_size` is zero.
+    pub fn read_size(mut self, read_size: usize) -> Self {
+        self.read_size = read_size;
+        self
+    }
+
+    /// Caps how much is read from the inner reader in one go, as
+    /// [`SaturatingReader::set_max_read_size`] does.
+    ///
+    /// # Panics
+    /// On [`Self::build`], if `max_read_size` is zero.
+    pub fn max_read_size(mut self, max_read_size: usize) -> Self {
+        self.max_read_size = max_read_size;
+        self
+    }
+
+    /// Lets the readahead grow up to `max_readahead` during sequential access, as
+    /// [`SaturatingReader::set_max_readahead`] does.
+    pub fn max_readahead(mut self, max_readahead: usize) -> Self {
+        self.max_readahead = Some(max_readahead);
+        self
+    }
+
+    /// Limits how much is held in the internal buffers, dropping the least recently used ones
+    /// once it's exceeded.
+    pub fn max_cached_bytes(mut self, max_cached_bytes: usize) -> Self {
+        self.max_cached_bytes = Some(max_cached_bytes);
+        self
+    }
+
+    /// Limits how many separate internal buffers are held, as
+    /// [`SaturatingReader::set_max_buffers`] does.
+    ///
+    /// # Panics
+    /// On [`Self::build`], if `max_buffers` is zero.
+    pub fn max_buffers(mut self, max_buffers: usize) -> Self {
+        self.max_buffers = Some(max_buffers);
+        self
+    }
+
+    /// Only holds on to data within `window` bytes either side of the cursor, as
+    /// [`SaturatingReader::with_window`] does.
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    /// Caps how much is ever read from the inner reader, as [`SaturatingReader::with_read_quota`]
+    /// does.
+    pub fn read_quota(mut self, read_quota: u64) -> Self {
+        self.read_quota = Some(read_quota);
+        self
+    }
+
+    /// Chooses how newly fetched data is combined with what's already buffered. By default it's
+    /// all merged together.
+    pub fn merge_policy(mut self, merge_policy: MergePolicy) -> Self {
+        self.merge_policy = merge_policy;
+        self
+    }
+
+    /// Keeps reading from the inner reader until each fetch is filled, rather than storing
+    /// whatever a single read returns. Worth turning on for inner readers which hand data over in
+    /// small pieces, such as pipes, so one miss doesn't turn into lots of tiny fetches. A fetch
+    /// still stops short at the end of the inner reader, or if it would block.
+    pub fn coalesce_partial_reads(mut self, coalesce_partial_reads: bool) -> Self {
+        self.coalesce_partial_reads = coalesce_partial_reads;
+        self
+    }
+
+    /// Retries failed reads from the inner reader as set out by `retry_policy`. By default they
+    /// aren't retried.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Calls `callback` with the final stats when the reader is dropped or consumed, so they can
+    /// be logged without having to remember to ask for them. The callback is only called once, so
+    /// clones of the reader, or of this builder, don't get it.
+    pub fn on_drop(mut self, callback: impl FnOnce(CacheStats) + Send + Sync + 'static) -> Self {
+        self.on_drop.set(callback);
+        self
+    }
+
+    pub fn build<R: Read + Seek>(self, inner: R) -> SaturatingReader<R> {
+        self.build_in(inner, HeapStore)
+    }
+
+    /// Builds a reader as [`Self::build`] does, which allocates the data for its buffers in
+    /// `store`.
+    pub fn build_in<R: Read + Seek, S: BufferStore>(
+        self,
+        inner: R,
+        store: S,
+    ) -> SaturatingReader<R, S> {
+        let mut reader = SaturatingReader {
+            cache: Cache::with_store(self.max_cached_bytes, store.clone()),
+            window: self.window.map(|window| window as u64),
+            read_quota: self.read_quota,
+            coalesce_partial_reads: self.coalesce_partial_reads,
+            retry_policy: self.retry_policy,
+            ..SaturatingReader::with_store(self.read_size, inner, store)
+        };
+        reader.set_max_read_size(self.max_read_size);
+        if let Some(max_readahead) = self.max_readahead {
+            reader.set_max_readahead(max_readahead);
+        }
+        if let Some(max_buffers) = self.max_buffers {
+            reader.set_max_buffers(max_buffers);
+        }
+        reader.cache.merge_policy = self.merge_policy;
+        reader.stats.on_drop = self.on_drop;
+
+        reader
+    }
 }
 
 impl<R: Read + Seek> SaturatingReader<R> {
@@ -16,34 +262,488 @@ impl<R: Read + Seek> SaturatingReader<R> {
         Self::with_capacity(8 * 1024, inner)
     }
 
+    /// Creates a reader which fetches at least `capacity` bytes from the inner reader on a miss.
+    ///
+    /// # Panics
+    /// If `capacity` is zero.
     pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        Self::with_store(capacity, inner, HeapStore)
+    }
+
+    /// Creates a reader which holds at most `max_bytes` in its internal buffers. Once the limit is
+    /// exceeded, the least recently used buffers are dropped.
+    pub fn with_capacity_and_limit(read_size: usize, max_bytes: usize, inner: R) -> Self {
+        SaturatingReaderBuilder::new()
+            .read_size(read_size)
+            .max_cached_bytes(max_bytes)
+            .build(inner)
+    }
+
+    /// Creates a reader which only holds on to data within `window_bytes` either side of the
+    /// cursor. After each read, anything further away is dropped, which bounds memory for
+    /// sequential scans while still allowing short re-reads behind the cursor.
+    ///
+    /// Readahead beyond the window is dropped along with everything else, so the window should be
+    /// larger than the read size.
+    pub fn with_window(window_bytes: usize, inner: R) -> Self {
+        SaturatingReaderBuilder::new()
+            .window(window_bytes)
+            .build(inner)
+    }
+
+    /// Creates a reader which reads at most `read_quota` bytes from the inner reader over its whole
+    /// lifetime. Fetches are cut short to fit within what's left, and once it's all used up they
+    /// fail with [`SaturatingError::ReadQuotaExceeded`]. Reads served from the internal buffers don't
+    /// count towards the quota.
+    pub fn with_read_quota(read_quota: u64, inner: R) -> Self {
+        SaturatingReaderBuilder::new()
+            .read_quota(read_quota)
+            .build(inner)
+    }
+}
+
+impl<R: Read + Seek, S: BufferStore> SaturatingReader<R, S> {
+    /// Creates a reader as [`Self::with_capacity`] does, which allocates the data for its buffers
+    /// in `store`.
+    ///
+    /// # Panics
+    /// If `capacity` is zero.
+    pub fn with_store(capacity: usize, inner: R, store: S) -> Self {
+        assert!(capacity > 0, "read size must be non-zero");
         Self {
             inner,
-            buffers: Vec::new(),
+            cache: Cache::with_store(None, store),
             cursor_pos: 0,
             bufread_size: capacity,
+            max_read_size: DEFAULT_MAX_READ_SIZE,
+            readahead: capacity,
+            max_readahead: capacity,
+            stats: TrackedStats::default(),
+            stream_len: None,
+            stream_len_hint: None,
+            inner_pos: None,
+            window: None,
+            fingerprint: None,
+            read_quota: None,
+            inner_bytes_read: 0,
+            peak_cached_bytes: 0,
+            coalesce_partial_reads: false,
+            logical_eof: None,
+            cancel: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// The minimum amount fetched from the inner reader on a miss.
+    pub fn read_size(&self) -> usize {
+        self.bufread_size
+    }
+
+    /// Changes the minimum amount fetched from the inner reader on a miss, taking effect from the
+    /// next fetch. If the readahead wasn't set up to grow, it follows the new read size.
+    ///
+    /// # Panics
+    /// If `read_size` is zero.
+    pub fn set_read_size(&mut self, read_size: usize) {
+        assert!(read_size > 0, "read size must be non-zero");
+        if self.max_readahead <= self.bufread_size {
+            self.max_readahead = read_size;
+        }
+        self.max_readahead = self.max_readahead.max(read_size);
+        self.bufread_size = read_size;
+        self.readahead = read_size;
+    }
+
+    /// Caps how much is read from the inner reader in one go, which defaults to
+    /// [`DEFAULT_MAX_READ_SIZE`]. Larger requests are fetched in several chunks instead, so a huge
+    /// request on a small inner reader doesn't allocate memory it'll never use.
+    ///
+    /// # Panics
+    /// If `max_read_size` is zero.
+    pub fn set_max_read_size(&mut self, max_read_size: usize) {
+        assert!(max_read_size > 0, "max read size must be non-zero");
+        self.max_read_size = max_read_size;
+    }
+
+    /// Retries failed reads from the inner reader as set out by `retry_policy`, from the next
+    /// fetch onwards.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Lets the amount read ahead on a miss grow during sequential access. Each miss which carries
+    /// on from where the last fetch ended doubles the readahead, up to `max_readahead`, and a miss
+    /// anywhere else drops it back to the read size. By default the readahead stays at the read
+    /// size.
+    pub fn set_max_readahead(&mut self, max_readahead: usize) {
+        self.max_readahead = max_readahead;
+        self.readahead = self.readahead.min(max_readahead).max(self.bufread_size);
+    }
+
+    /// Limits how many separate internal buffers are held, as lots of small ones are slow to look
+    /// through. Once there are too many, the least recently used are dropped, as for the memory
+    /// limit. The limit applies from the next fetch onwards.
+    ///
+    /// How many buffers there are depends on the [`MergePolicy`]. By default touching buffers are
+    /// merged, so only disjoint ranges count separately, and [`MergePolicy::BridgeGaps`] merges
+    /// across small gaps too. Under [`MergePolicy::Never`] every fetch adds buffers of its own, so
+    /// the cap is reached quickly and even a sequential scan starts dropping what it read
+    /// earlier.
+    ///
+    /// # Panics
+    /// If `max_buffers` is zero.
+    pub fn set_max_buffers(&mut self, max_buffers: usize) {
+        assert!(max_buffers > 0, "max buffers must be non-zero");
+        self.cache.set_max_buffers(max_buffers);
+    }
+
+    /// Makes room for at least `additional` more internal buffers, so filling in lots of disjoint
+    /// ranges, such as the entries of an index, doesn't keep reallocating as it goes.
+    pub fn reserve_buffers(&mut self, additional: usize) {
+        self.cache.reserve_buffers(additional);
+    }
+
+    /// Drops everything held in the internal buffers. The cursor is left where it is.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Drops any buffered data within `[start, end)`, so it will be fetched again on the next
+    /// read. Buffers which only partially overlap the range are trimmed down to what's left,
+    /// which splits them in two if the range falls in the middle. The cursor is left where it is.
+    pub fn invalidate(&mut self, start: u64, end: u64) {
+        self.cache.invalidate(start, end);
+    }
+
+    /// Keeps `[start, end)` buffered regardless of the memory limit, so eviction will only ever
+    /// drop data around it. Anything in the range which isn't buffered yet is fetched now.
+    /// Clearing or invalidating the range still drops it, but it stays pinned for when it's next
+    /// read.
+    pub fn pin(&mut self, start: u64, end: u64) -> std::io::Result<()> {
+        self.cache.pin(start, end);
+        self.fetch_range(start, end.saturating_sub(start))
+    }
+
+    /// Lets eviction drop anything buffered within `[start, end)` again, undoing [`Self::pin`].
+    pub fn unpin(&mut self, start: u64, end: u64) {
+        self.cache.unpin(start, end);
+    }
+
+    /// Marks `[start, end)` as a hole in a sparse source, which holds nothing but zeros. Reads over
+    /// it are filled with zeros rather than fetched, and it isn't held in the internal buffers,
+    /// though it counts as cached everywhere else. Anything already buffered there is still served
+    /// as it is.
+    pub fn mark_hole(&mut self, start: u64, end: u64) {
+        self.cache.mark_hole(start, end);
+    }
+
+    /// Frees the memory holding buffered data within `[start, end)`, as a hint that it won't be
+    /// needed again soon. Unlike [`Self::invalidate`], the data isn't considered stale, but the
+    /// effect is the same: buffers across the boundaries are split, and reading the range again
+    /// fetches it afresh.
+    pub fn drop_range(&mut self, start: u64, end: u64) {
+        self.cache.invalidate(start, end);
+    }
+
+    /// Merges any internal buffers which sit end to end into single buffers. The buffered data
+    /// doesn't change, but there are fewer buffers to look through on each read.
+    pub fn coalesce_adjacent(&mut self) {
+        self.cache.coalesce_adjacent();
+    }
+
+    /// Calls `callback` whenever data is fetched, served from the internal buffers, or evicted,
+    /// replacing any callback given before. Clones of the reader share the callback.
+    pub fn on_event(&mut self, callback: impl FnMut(CacheEvent) + Send + 'static) {
+        self.cache.events.set(callback);
+    }
+
+    /// Frees any memory the internal buffers hold beyond the buffered data, such as what's left
+    /// over after buffers are split or dropped. Worth calling now and then on long-lived readers.
+    pub fn shrink_to_fit(&mut self) {
+        self.cache.shrink_to_fit();
+    }
+
+    /// Puts the internal buffers into a canonical form, with every run of touching buffers merged
+    /// into one and no spare capacity, so the layout only depends on what's buffered and not on
+    /// how it got there.
+    pub fn normalize(&mut self) {
+        self.cache.buffers.sort_by_key(|b| b.range().0);
+        self.coalesce_adjacent();
+        self.shrink_to_fit();
+    }
+
+    /// Returns the index into [`Self::cached_ranges`] of the buffer holding `offset`, or if there
+    /// isn't one, the index a buffer starting there would go.
+    pub fn buffer_index_for(&self, offset: u64) -> Result<usize, usize> {
+        self.cache.buffer_index_for(offset)
+    }
+
+    /// Returns `[offset, offset + len)` straight out of the internal buffers without copying it, if
+    /// it's all held in a single buffer. Ranges which are only partly buffered, or which span
+    /// several buffers, give `None`, as do corrupted buffers with the `checksum` feature. The
+    /// cursor isn't moved.
+    pub fn borrow_range(&self, offset: u64, len: u64) -> Option<&[u8]> {
+        self.cache.get_range(offset, len)
+    }
+
+    /// Returns read-only access to the internal buffers, for serving cached data without needing
+    /// a mutable borrow of the reader.
+    pub fn cache(&self) -> CacheView<'_, S> {
+        CacheView::new(&self.cache)
+    }
+
+    /// Returns the ranges `[start, end)` held in the internal buffers, ordered by start.
+    pub fn cached_ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.cache.cached_ranges()
+    }
+
+    /// Returns a reader over `[offset, offset + length)` if it's all buffered, without copying it
+    /// or touching the inner reader.
+    pub fn cached_reader(&self, offset: u64, length: u64) -> Option<impl Read + '_> {
+        let data = self.cache.get_prefix(offset)?;
+        data.get(..usize::try_from(length).ok()?)
+    }
+
+    /// Returns the start offset and data of each internal buffer, ordered by start. Together these
+    /// are everything that's buffered, less any buffers found to be corrupted with the `checksum`
+    /// feature.
+    pub fn cached_chunks(&self) -> impl Iterator<Item = (u64, &[u8])> + '_ {
+        self.cache.cached_chunks()
+    }
+
+    /// Writes out everything held in the internal buffers, so it can be restored with
+    /// [`Self::load_cache`] later on. Buffers found to be corrupted with the `checksum` feature are
+    /// left out.
+    pub fn save_cache<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let buffers = self.cache.buffers.iter().filter(|b| b.verify());
+        persist::write_buffers(w, buffers.collect::<Vec<_>>().into_iter())
+    }
+
+    /// Restores buffers written out by [`Self::save_cache`], merging them with anything that's
+    /// already buffered. Data that wasn't written by a compatible version is rejected.
+    pub fn load_cache<Rd: Read>(&mut self, r: &mut Rd) -> std::io::Result<()> {
+        for buffer in persist::read_buffers(r)? {
+            self.store(buffer.range().0, buffer.data());
+        }
+
+        Ok(())
+    }
+
+    /// Writes out just the ranges which are buffered, without their data, in a compact format
+    /// which can be read back with [`import_coverage`]. The format is described in full in the
+    /// source, but in short it's a varint count followed by varint gap and length pairs.
+    pub fn export_coverage<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        persist::write_coverage(w, self.cached_ranges())
+    }
+
+    /// Returns counters describing how reads have been served so far.
+    pub fn stats(&self) -> CacheStats {
+        *self.stats
+    }
+
+    /// Returns the total number of bytes read from the inner reader over the reader's lifetime,
+    /// including any read ahead that was never asked for. Unlike [`CacheStats::bytes_read_from_inner`]
+    /// this isn't affected by [`Self::reset_stats`], so it can be used for cost accounting.
+    pub fn inner_bytes_read(&self) -> u64 {
+        self.inner_bytes_read
+    }
+
+    /// Returns the most that's been held in the internal buffers at once over the reader's
+    /// lifetime, which is worth knowing before settling on a memory limit. Dropping buffered data
+    /// doesn't bring it back down, and unlike [`CacheStats::peak_cached_bytes`] it isn't affected
+    /// by [`Self::reset_stats`].
+    pub fn peak_cached_bytes(&self) -> u64 {
+        self.peak_cached_bytes
+    }
+
+    /// Sets all of the counters returned by [`Self::stats`] back to zero.
+    pub fn reset_stats(&mut self) {
+        *self.stats = CacheStats::default();
+    }
+
+    /// Returns the total number of bytes held in the internal buffers.
+    pub fn cached_bytes(&self) -> u64 {
+        self.cache.cached_bytes()
+    }
+
+    /// Returns the length of the inner reader. This seeks the inner reader to its end the first
+    /// time round, then the length is remembered for any later calls. The cursor isn't moved.
+    /// Reads aren't held to the length found, so they carry on past it if the inner reader grows.
+    pub fn stream_len(&mut self) -> std::io::Result<u64> {
+        if let Some(len) = self.stream_len_hint.or(self.stream_len) {
+            return Ok(len);
+        }
+
+        // No need to restore the inner position, as reads always reconcile it with the cursor
+        self.inner_pos = None;
+        let len = self.inner.seek(SeekFrom::End(0))?;
+        self.stream_len = Some(len);
+        self.inner_pos = Some(len);
+
+        Ok(len)
+    }
+
+    /// Tells the reader how long the inner reader is, for sources which can't seek from the end or
+    /// where doing so is expensive, such as a decompressing reader. The hint is trusted: lengths
+    /// and seeks from the end are worked out from it without touching the inner reader, and reads
+    /// stop short at it.
+    pub fn set_stream_len_hint(&mut self, len: u64) {
+        self.stream_len_hint = Some(len);
+    }
+
+    /// Forgets the length of the inner reader, along with any hint, so it's looked up again the
+    /// next time it's needed. The length is only found out once otherwise, so this is for sources
+    /// which have grown or shrunk since. Writes through the reader take care of this themselves.
+    pub fn invalidate_stream_len(&mut self) {
+        self.stream_len = None;
+        self.stream_len_hint = None;
+    }
+
+    /// Makes reads stop at `len` as if the inner reader ended there, even if it has more, and
+    /// nothing past it is fetched. Useful for parsing a snapshot of a file which is still being
+    /// appended to. The length of the stream, and seeks from its end, aren't affected.
+    pub fn set_logical_eof(&mut self, len: u64) {
+        self.logical_eof = Some(len);
+    }
+
+    /// Goes back to reading up to wherever the inner reader ends.
+    pub fn clear_logical_eof(&mut self) {
+        self.logical_eof = None;
+    }
+
+    /// Gives the reader a flag which abandons fetches from the inner reader once it's set, so
+    /// another thread can put a bound on how long a slow source holds things up. The flag is
+    /// checked before each read from the inner reader, and a cancelled fetch fails with
+    /// [`SaturatingError::Cancelled`], under [`std::io::ErrorKind::Interrupted`]. Reads served
+    /// from the internal buffers go ahead as normal.
+    ///
+    /// Note that the standard library retries interrupted reads, so [`Read::read_exact`] and
+    /// [`Read::read_to_end`] will spin while the flag is set. Use [`Read::read`] or
+    /// [`Self::read_at`] instead.
+    pub fn set_cancel_flag(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = Some(cancel);
+    }
+
+    /// Drops the cancel flag, so fetches always go ahead.
+    pub fn clear_cancel_flag(&mut self) {
+        self.cancel = None;
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Where reads stop, if that's known up front. Only the hint and the logical end count, as
+    /// the inner reader may have grown past any length we found for ourselves.
+    fn read_limit(&self) -> Option<u64> {
+        match (self.stream_len_hint, self.logical_eof) {
+            (Some(len), Some(eof)) => Some(len.min(eof)),
+            (len, eof) => len.or(eof),
+        }
+    }
+
+    /// How much of `len` bytes from `offset` lies before the read limit.
+    fn clip_to_limit(&self, offset: u64, len: usize) -> usize {
+        match self.read_limit() {
+            Some(limit) => limit.saturating_sub(offset).min(len as u64) as usize,
+            None => len,
+        }
+    }
+
+    /// Returns how many bytes are buffered contiguously from the cursor onwards, which can be read
+    /// without touching the inner reader. Unlike [`BufRead::fill_buf`], nothing is fetched if
+    /// there's nothing buffered. Holes count as buffered.
+    pub fn available(&self) -> u64 {
+        let offset = self.cursor_pos;
+        let end = match self.cache.get_prefix(offset) {
+            Some(data) => offset + data.len() as u64,
+            None => self.cache.hole_end(offset).unwrap_or(offset),
+        };
+
+        end.min(self.read_limit().unwrap_or(u64::MAX))
+            .saturating_sub(offset)
+    }
+
+    /// Returns the position of the cursor, without touching the inner reader.
+    pub fn position(&self) -> u64 {
+        self.cursor_pos
+    }
+
+    /// Moves the cursor to the given position, without touching the inner reader. This isn't
+    /// checked against the length of the stream, so reads from past the end will come up empty.
+    pub fn set_position(&mut self, pos: u64) {
+        self.cursor_pos = pos;
+    }
+
+    /// Returns a reference to the inner reader. Its position may not match the cursor, as it is
+    /// updated lazily during reads.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader. Moving the inner reader is fine, but
+    /// anything which changes the data it holds won't be reflected in the internal buffers, so
+    /// affected ranges should be passed to [`Self::invalidate`].
+    pub fn get_mut(&mut self) -> &mut R {
+        // We can't know where the inner reader will be left afterwards
+        self.inner_pos = None;
+        &mut self.inner
+    }
+
+    /// Consumes the reader, returning everything it buffered as one `Vec` if that's the whole
+    /// stream, as it is after [`Self::load_all`]. This is only the case if there's a single buffer
+    /// starting at zero, and if the stream length is known, it has to end there too. Otherwise, or
+    /// if the buffer has been corrupted with the `checksum` feature, `None` is returned.
+    pub fn into_cached_vec(mut self) -> Option<Vec<u8>> {
+        let [buffer] = self.cache.buffers.as_slice() else {
+            return None;
+        };
+        let (start, end) = buffer.range();
+        let len = self.stream_len_hint.or(self.stream_len);
+        if start != 0 || len.is_some_and(|len| len != end) || !buffer.verify() {
+            return None;
         }
+
+        self.cache.take_buffers().pop().map(Buffer::into_data)
     }
 
-    /// Adds a new buffer to the internally maintained set. Overlapping buffers are merged together
-    /// for optimisation.
-    fn add_buffer(&mut self, offset: u64, buf: &[u8]) {
-        let new_buffer = Buffer::from_slice(offset, buf);
+    /// Consumes the reader, returning the inner reader, the internal buffers ordered by start, and
+    /// the cursor position. These can be given to [`Self::from_parts`] to carry the buffered data
+    /// over to a new reader. Buffers found to be corrupted with the `checksum` feature are left
+    /// out.
+    pub fn into_parts(mut self) -> (R, Vec<Buffer<S>>, u64) {
+        let mut buffers = self.cache.take_buffers();
+        buffers.retain(|b| b.verify());
 
-        // Pull out all overlapping buffers
-        // todo: replace with https://github.com/rust-lang/rfcs/issues/2140 once it has stabilised
-        let buffers = std::mem::take(&mut self.buffers);
-        let (overlapping, non_overlapping): (Vec<_>, Vec<_>) =
-            buffers.into_iter().partition(|x| x.overlaps(&new_buffer));
-        self.buffers = non_overlapping;
+        (self.inner, buffers, self.cursor_pos)
+    }
 
-        // Merge the overlapping buffers
-        let new_buffer = overlapping
-            .into_iter()
-            .fold(new_buffer, |acc, x| acc.merge(x));
+    /// Creates a reader which starts off holding the given buffers, with its cursor at
+    /// `cursor_pos`. The buffers may be in any order, and any which overlap are merged together.
+    /// Their data is trusted to match the inner reader.
+    pub fn from_parts(
+        inner: R,
+        buffers: Vec<Buffer<S>>,
+        cursor_pos: u64,
+        bufread_size: usize,
+    ) -> Self {
+        Self {
+            cache: Cache::from_buffers(buffers),
+            cursor_pos,
+            ..Self::with_store(bufread_size, inner, S::default())
+        }
+    }
 
-        // Add the new buffer into the collection
-        self.buffers.push(new_buffer);
+    /// Creates a reader which starts off holding the given buffers, as [`Self::from_parts`] does
+    /// but with the cursor at the start and the default read size. A [`Buffer`] always holds
+    /// exactly as much data as its range covers, so there's nothing further to check.
+    pub fn with_buffers(inner: R, buffers: Vec<Buffer<S>>) -> Self {
+        Self {
+            cache: Cache::from_buffers(buffers),
+            ..Self::with_store(8 * 1024, inner, S::default())
+        }
     }
 
     /// Consumes the reader, returning the inner reader. Note that the cursor position may not be
@@ -52,120 +752,1074 @@ impl<R: Read + Seek> SaturatingReader<R> {
         self.inner
     }
 
-    /// Reads from the inner reader, storing it in the buffer. If the requested anount is small,
-    /// buffer it up to a minimum.
-    fn read_inner(&mut self, at_least: usize) -> std::io::Result<usize> {
-        let inner_pos = self.inner.stream_position()?;
-        self.inner
-            .seek_relative(self.cursor_pos as i64 - inner_pos as i64)?;
+    /// Drops any buffered data outside of the window around the cursor, if there is one. Pinned
+    /// ranges are kept wherever they are.
+    fn slide_window(&mut self) {
+        let Some(window) = self.window else {
+            return;
+        };
 
-        // If not, we fetch the range from the underlying reader
-        let mut buf = vec![0; at_least.max(self.bufread_size)];
-        let num_bytes_read = self.inner.read(&mut buf)?;
+        self.cache
+            .invalidate_unpinned(0, self.cursor_pos.saturating_sub(window));
+        self.cache
+            .invalidate_unpinned(self.cursor_pos.saturating_add(window), u64::MAX);
+    }
 
-        // Then we store the fetched data in a new buffer internally
-        self.add_buffer(self.cursor_pos, &buf[..num_bytes_read]);
+    /// Reads from the inner reader at the given offset, storing it in the buffer. If the requested
+    /// anount is small, buffer it up to a minimum. The read is capped at the max read size, so
+    /// large requests may need several calls.
+    fn read_inner(&mut self, offset: u64, at_least: usize) -> std::io::Result<usize> {
+        // Carrying on from the last fetch looks like a sequential scan, so read further ahead
+        self.readahead = if self.inner_pos == Some(offset) {
+            self.readahead.saturating_mul(2).min(self.max_readahead)
+        } else {
+            self.bufread_size
+        }
+        .max(self.bufread_size);
+
+        let mut len = at_least.max(self.readahead).min(self.max_read_size);
+        // Don't read ahead past the end of the stream, if we know where that is
+        if let Some(limit) = self.read_limit() {
+            len = len.min(
+                limit
+                    .saturating_sub(offset)
+                    .try_into()
+                    .unwrap_or(usize::MAX),
+            );
+        }
+        let mut buf = vec![0; len];
+        let num_bytes_read = self.fetch_uncached(offset, &mut buf)?;
+        if num_bytes_read > 0 {
+            // Hand the fetched data over as it is, rather than storing a copy. It's only trimmed
+            // down if the inner reader came up short.
+            buf.truncate(num_bytes_read);
+            buf.shrink_to_fit();
+            self.store(offset, buf);
+        }
+        self.after_fetch(offset, num_bytes_read);
 
         Ok(num_bytes_read)
     }
-}
 
-impl<R: Seek + Read> Read for SaturatingReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        // todo: If requested range partially exists in the internal buffers, try to re-use as much
-        // as possible before fetching the rest from inner.
+    /// Reads from the inner reader at the given offset straight into `buf`, then stores a copy of
+    /// what was read in the buffer. At most the max read size is read.
+    fn read_inner_into(&mut self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let num_bytes_read = self.fetch_into(offset, buf)?;
+        self.after_fetch(offset, num_bytes_read);
 
-        // First check if the range exists in the maintained buffers
-        let existing_buffer = self
-            .buffers
-            .iter()
-            .find_map(|b| b.get_range(self.cursor_pos, buf.len() as u64));
+        Ok(num_bytes_read)
+    }
 
-        // Copy out from internal buffer if it exists
-        if let Some(existing_buffer) = existing_buffer {
-            buf.copy_from_slice(existing_buffer);
-            self.cursor_pos += buf.len() as u64;
-            return Ok(buf.len());
+    /// Carries out the merge policy once `num_bytes_read` have been fetched and stored at
+    /// `offset`.
+    fn after_fetch(&mut self, offset: u64, num_bytes_read: usize) {
+        if let MergePolicy::BridgeGaps(max_gap) = self.cache.merge_policy {
+            if num_bytes_read > 0 {
+                self.bridge_gaps(offset, max_gap);
+            }
         }
+    }
 
-        // If not, we'll read from the inner reader
-        self.read_inner(buf.len())?;
+    /// Fetches any small gaps either side of the buffer holding `offset`, so it can be merged with
+    /// its neighbours.
+    fn bridge_gaps(&mut self, offset: u64, max_gap: u64) {
+        for (gap_start, gap_end) in self.cache.gaps_around(offset) {
+            if gap_end - gap_start > max_gap {
+                continue;
+            }
 
-        // Then we re-call the read function with the loaded data.
-        self.read(buf)
+            // This is only an optimisation, so if it fails the read it's part of shouldn't. The
+            // gap will just be fetched as normal later.
+            let mut buf = vec![0; (gap_end - gap_start) as usize];
+            let _ = self.fetch_into(gap_start, &mut buf);
+        }
     }
-}
 
-impl<R: Read + Seek> Seek for SaturatingReader<R> {
-    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        match pos {
-            // For start/current, don't seek the underlying reader. It will be handled in read() if
-            // needed.
-            SeekFrom::Start(p) => self.cursor_pos = p,
-            SeekFrom::Current(p) => {
-                self.cursor_pos = self.cursor_pos.checked_add_signed(p).ok_or_else(|| {
-                    std::io::Error::new(ErrorKind::Other, "Seek position underflowed.")
-                })?;
+    /// Does the fetching for [`Self::read_inner_into`], without bridging any gaps.
+    fn fetch_into(&mut self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let num_bytes_read = self.fetch_uncached(offset, buf)?;
+
+        // The inner reader is exhausted, so there's nothing to store
+        if num_bytes_read == 0 {
+            return Ok(0);
+        }
+
+        // Then we store the fetched data in a new buffer internally
+        self.store(offset, &buf[..num_bytes_read]);
+
+        Ok(num_bytes_read)
+    }
+
+    /// Adds `data` to the internal buffers, keeping track of the most they've held at once. This
+    /// counts everything held before eviction brings it back within the limit.
+    fn store<'a>(&mut self, offset: u64, data: impl Into<Cow<'a, [u8]>>) {
+        let cached_bytes = self.cache.add_buffer(offset, data);
+        self.stats.peak_cached_bytes = self.stats.peak_cached_bytes.max(cached_bytes);
+        self.peak_cached_bytes = self.peak_cached_bytes.max(cached_bytes);
+    }
+
+    /// Reads from the inner reader at the given offset straight into `buf`, without storing
+    /// anything. At most the max read size is read, and less if that would go over the quota.
+    fn fetch_uncached(&mut self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Nothing is needed, such as at the end of the stream, so it can't go over the quota
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.is_cancelled() {
+            return Err(SaturatingError::Cancelled.into());
+        }
+        let mut len = buf.len().min(self.max_read_size);
+        if let Some(quota) = self.read_quota {
+            let remaining = quota - self.inner_bytes_read;
+            if remaining == 0 {
+                return Err(SaturatingError::ReadQuotaExceeded { quota }.into());
             }
-            // Our inner might not support seeking from end, so defer to its implementation
-            // instead.
-            SeekFrom::End(_) => {
-                self.inner.seek(pos)?;
-                self.cursor_pos = self.inner.stream_position()?;
+            len = len.min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        }
+        let buf = &mut buf[..len];
+        // Only move the inner reader if it isn't already where we need it. Forget where it is
+        // while we do, in case something fails part way.
+        let inner_pos = match self.inner_pos.take() {
+            Some(inner_pos) => inner_pos,
+            None => self.inner.stream_position()?,
+        };
+        if inner_pos != offset {
+            self.inner.seek_relative(offset as i64 - inner_pos as i64)?;
+        }
+
+        // If not, we fetch the range from the underlying reader
+        let mut retry = 0;
+        let num_bytes_read = loop {
+            let result = if self.coalesce_partial_reads {
+                self.read_inner_fully(buf)
+            } else {
+                self.inner.read(buf)
+            };
+            match result {
+                Err(e)
+                    if retry < self.retry_policy.max_retries
+                        && RetryPolicy::is_retryable(&e)
+                        && !self.is_cancelled() =>
+                {
+                    std::thread::sleep(self.retry_policy.delay(retry));
+                    retry += 1;
+                    // The failed read may have left the inner reader anywhere
+                    self.inner.seek(SeekFrom::Start(offset))?;
+                }
+                result => break result?,
             }
         };
+        self.inner_pos = Some(offset + num_bytes_read as u64);
+        self.stats.misses += 1;
+        self.stats.bytes_read_from_inner += num_bytes_read as u64;
+        self.inner_bytes_read += num_bytes_read as u64;
+        self.cache.events.emit(CacheEvent::Fetch {
+            offset,
+            len: num_bytes_read as u64,
+        });
 
-        Ok(self.cursor_pos)
+        Ok(num_bytes_read)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io::{Cursor, Read, Seek, SeekFrom};
+    /// Reads from the inner reader until `buf` is full, it runs out, or it would block. An error
+    /// after some data has been read is left to come up again on the next read.
+    fn read_inner_fully(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            // Keep what we've got so far, as for an error
+            if filled > 0 && self.is_cancelled() {
+                break;
+            }
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(num_bytes_read) => filled += num_bytes_read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) if filled == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
 
-    use super::SaturatingReader;
+        Ok(filled)
+    }
 
-    #[test]
-    fn test1() {
-        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
-        let mut bufreader = SaturatingReader::new(reader);
+    /// Reads from the given offset without moving the cursor. Data is served from the internal
+    /// buffers if possible, and anything missing is fetched and kept for later.
+    ///
+    /// With the `checksum` feature, buffers are checked before their data is reused, and any which
+    /// have been corrupted are dropped and fetched again.
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        // If we know where the stream ends, there's no point asking for anything past it
+        let buf = match self.read_limit() {
+            Some(limit) => {
+                let available = limit.saturating_sub(offset).min(buf.len() as u64);
+                &mut buf[..available as usize]
+            }
+            None => buf,
+        };
 
-        let mut buf = [0; 64];
-        bufreader.read_exact(&mut buf).unwrap();
-        println!("{:?}", bufreader);
-        bufreader.read_exact(&mut buf).unwrap();
-        println!("{:?}", bufreader);
+        // Nothing to do, and it shouldn't count towards the stats either
+        if buf.is_empty() {
+            return Ok(0);
+        }
 
-        let mut buf = vec![];
-        let n = bufreader.read_to_end(&mut buf).unwrap();
-        println!("{:?} {:?}", n, bufreader);
-    }
+        #[cfg(feature = "checksum")]
+        self.cache
+            .drop_corrupt(offset, offset.saturating_add(buf.len() as u64));
 
-    #[test]
-    fn test2() {
-        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
-        let mut bufreader = SaturatingReader::new(reader);
+        // Alternate between copying out what's in the maintained buffers and fetching the next
+        // gap from the inner reader, until the request is filled.
+        let mut filled = 0;
+        let mut fetched = false;
+        // Bytes from the last fetch, which are the first to be copied out on the next pass.
+        // Anything copied beyond those was already buffered.
+        let mut fresh = 0;
+        let mut reused = 0;
+        while filled < buf.len() {
+            // Copy out everything up until the first gap
+            let (num_bytes_copied, gap) = self
+                .cache
+                .read_cached(offset + filled as u64, &mut buf[filled..]);
+            let hit = num_bytes_copied.saturating_sub(fresh);
+            if hit > 0 {
+                self.cache.events.emit(CacheEvent::Hit {
+                    offset: offset + (filled + fresh) as u64,
+                    len: hit as u64,
+                });
+            }
+            filled += num_bytes_copied;
 
-        let mut buf = [0; 64];
-        bufreader.read_exact(&mut buf).unwrap();
+            self.stats.bytes_served_from_cache += hit as u64;
+            reused += hit as u64;
 
-        // Repeated
-        bufreader.seek(SeekFrom::Start(0)).unwrap();
-        bufreader.read_exact(&mut buf).unwrap();
+            // Holes are filled in along with the buffered data, so any gap needs fetching
+            let Some((gap_start, gap_end)) = gap else {
+                break;
+            };
 
-        assert_eq!(buf.as_slice(), (0..64).collect::<Vec<_>>().as_slice());
-        assert_eq!(bufreader.buffers.len(), 1);
-        assert_eq!(bufreader.buffers[0].range(), (0, 64));
-        println!("{:?}", bufreader.buffers);
+            fetched = true;
+            let gap_len = (gap_end - gap_start) as usize;
+            // If the gap is big enough that there's no readahead to do, skip the intermediate
+            // buffer and read straight into the caller's
+            let direct = gap_len >= self.bufread_size;
+            let result = if direct {
+                self.read_inner_into(gap_start, &mut buf[filled..filled + gap_len])
+            } else {
+                self.read_inner(gap_start, gap_len)
+            };
+            match result {
+                // The inner reader is exhausted, so this is a short read
+                Ok(0) => break,
+                Ok(num_bytes_read) if direct => {
+                    filled += num_bytes_read;
+                    fresh = 0;
+                }
+                Ok(num_bytes_read) => fresh = num_bytes_read,
+                Err(e) if filled == 0 => return Err(e),
+                // We've already handed over some bytes, so report those instead. The error will
+                // come up again on the next read.
+                Err(_) => break,
+            }
+        }
 
-        // Partial overlap
-        bufreader.seek(SeekFrom::Start(32)).unwrap();
-        bufreader.read_exact(&mut buf).unwrap();
+        if fetched {
+            self.stats.bytes_served_from_partial_hits += reused;
+        } else {
+            self.stats.hits += 1;
+        }
+        self.cache.touch(offset, offset + filled as u64);
 
-        assert_eq!(buf.as_slice(), (32..96).collect::<Vec<_>>().as_slice());
-        assert_eq!(bufreader.buffers.len(), 1);
-        assert_eq!(bufreader.buffers[0].range(), (0, 96));
-        println!("{:?}", bufreader.buffers);
+        Ok(filled)
+    }
+
+    /// Reads exactly enough to fill `buf` from the given offset, without moving the cursor. If the
+    /// inner reader runs out first, an [`std::io::ErrorKind::UnexpectedEof`] error is returned and
+    /// the contents of `buf` are unspecified.
+    pub fn read_exact_at(&mut self, mut offset: u64, mut buf: &mut [u8]) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(offset, buf) {
+                Ok(0) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ))
+                }
+                Ok(num_bytes_read) => {
+                    offset += num_bytes_read as u64;
+                    buf = &mut buf[num_bytes_read..];
+                }
+                // Cancelled fetches come up as interrupted too, but those shouldn't be retried
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted && !self.is_cancelled() => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads all of `[offset, offset + len)` into a new `Vec`, without moving the cursor, as for
+    /// fixed size structures in a binary format. Buffered parts of the range are reused. If the
+    /// inner reader is too short, an [`std::io::ErrorKind::UnexpectedEof`] error is returned, and
+    /// if the length of the stream is already known this is checked before anything is read.
+    pub fn read_range_exact(&mut self, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+        let too_short = || {
+            std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("source is too short to read {len} bytes at {offset}"),
+            )
+        };
+        // Don't allocate space for a range we already know can't be read
+        let end = offset.checked_add(len).ok_or_else(too_short)?;
+        // The stream may have grown since we found its length, so look again before giving up
+        if self.stream_len.is_some_and(|len| len < end) {
+            self.stream_len = None;
+            self.stream_len()?;
+        }
+        let limit = match (self.read_limit(), self.stream_len) {
+            (Some(limit), Some(len)) => Some(limit.min(len)),
+            (limit, len) => limit.or(len),
+        };
+        if limit.is_some_and(|limit| limit < end) {
+            return Err(too_short());
+        }
+
+        let mut buf = vec![0; usize::try_from(len).map_err(|_| too_short())?];
+        self.read_exact_at(offset, &mut buf)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => too_short(),
+                _ => e,
+            })?;
+
+        Ok(buf)
+    }
+
+    /// Copies out whatever is buffered contiguously from the cursor, up to the size of `buf`, and
+    /// moves the cursor past it. If that doesn't fill `buf`, the range `[start, end)` which is
+    /// still needed is returned too, so it can be fetched later. The inner reader isn't touched.
+    pub fn read_available(&mut self, buf: &mut [u8]) -> (usize, Option<(u64, u64)>) {
+        let offset = self.cursor_pos;
+        // Nothing past the read limit is needed, so it's never reported as missing either
+        let len = self.clip_to_limit(offset, buf.len());
+        let buf = &mut buf[..len];
+        let end = offset.saturating_add(buf.len() as u64);
+        #[cfg(feature = "checksum")]
+        self.cache.drop_corrupt(offset, end);
+
+        let (num_bytes_copied, gap) = self.cache.read_cached(offset, buf);
+        if num_bytes_copied > 0 {
+            self.cache.events.emit(CacheEvent::Hit {
+                offset,
+                len: num_bytes_copied as u64,
+            });
+        }
+        if gap.is_none() {
+            self.stats.hits += 1;
+        }
+        self.stats.bytes_served_from_cache += num_bytes_copied as u64;
+        self.cache.touch(offset, offset + num_bytes_copied as u64);
+        self.cursor_pos += num_bytes_copied as u64;
+        self.slide_window();
+
+        (num_bytes_copied, gap.map(|_| (self.cursor_pos, end)))
+    }
+
+    /// Reads from the inner reader at the cursor straight into `buf`, moving the cursor along
+    /// but leaving the internal buffers alone, so huge one-off reads can be streamed past a
+    /// bounded cache without pushing anything out of it. Nothing is served from the buffers
+    /// either, even if they hold the data.
+    pub fn read_uncached(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = match self.read_limit() {
+            Some(limit) => limit.saturating_sub(self.cursor_pos).min(buf.len() as u64) as usize,
+            None => buf.len(),
+        };
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let num_bytes_read = self.fetch_uncached(self.cursor_pos, &mut buf[..len])?;
+        self.cursor_pos += num_bytes_read as u64;
+        self.slide_window();
+
+        Ok(num_bytes_read)
+    }
+
+    /// Copies everything from the cursor to the end of the inner reader into `writer`, moving the
+    /// cursor to the end. Buffered data is written out directly, so only the gaps are fetched.
+    /// Returns the number of bytes copied.
+    pub fn copy_to<W: Write>(&mut self, writer: &mut W) -> std::io::Result<u64> {
+        let mut copied = 0;
+        loop {
+            // Fetch up to the next buffer or hole, rather than a whole read size over the top of it
+            if self.cache.get_prefix_or_hole(self.cursor_pos).is_none() {
+                let next = self
+                    .cache
+                    .buffers
+                    .partition_point(|b| b.range().0 <= self.cursor_pos);
+                let gap_end = self
+                    .cache
+                    .buffers
+                    .get(next)
+                    .map_or(u64::MAX, |b| b.range().0)
+                    .min(
+                        self.cache
+                            .next_hole(self.cursor_pos)
+                            .map_or(u64::MAX, |h| h.0),
+                    )
+                    .min(self.read_limit().unwrap_or(u64::MAX))
+                    .max(self.cursor_pos);
+                let len = (gap_end - self.cursor_pos).min(self.bufread_size as u64);
+                // At the end already, so there's nothing to fetch
+                if len == 0 {
+                    break;
+                }
+                if self.read_inner_into(self.cursor_pos, &mut vec![0; len as usize])? == 0 {
+                    break;
+                }
+            }
+
+            let data = self.fill_buf()?;
+            if data.is_empty() {
+                break;
+            }
+            writer.write_all(data)?;
+
+            let len = data.len();
+            self.consume(len);
+            copied += len as u64;
+        }
+
+        Ok(copied)
+    }
+
+    /// Reads from the cursor to the end of the inner reader in pieces of `chunk_size`, moving the
+    /// cursor along and buffering as usual. Only the last chunk may be short, unless a read fails
+    /// part way through a chunk, in which case the error comes up next.
+    pub fn chunks(
+        &mut self,
+        chunk_size: usize,
+    ) -> impl Iterator<Item = std::io::Result<Vec<u8>>> + '_ {
+        std::iter::from_fn(move || {
+            let mut chunk = vec![0; chunk_size];
+            match self.read(&mut chunk) {
+                Ok(0) => None,
+                Ok(num_bytes_read) => {
+                    chunk.truncate(num_bytes_read);
+                    Some(Ok(chunk))
+                }
+                Err(e) => Some(Err(e)),
+            }
+        })
+    }
+
+    /// Reads from the cursor only if all of `buf` can be filled from the internal buffers,
+    /// advancing the cursor as usual. Otherwise nothing is read and `None` is returned, leaving
+    /// the inner reader untouched, so the caller can decide whether to wait or fetch it later.
+    pub fn try_read_cached(&mut self, buf: &mut [u8]) -> std::io::Result<Option<usize>> {
+        let (offset, len) = (self.cursor_pos, buf.len() as u64);
+        #[cfg(feature = "checksum")]
+        self.cache.drop_corrupt(offset, offset.saturating_add(len));
+        if !self.is_cached(offset, len) {
+            return Ok(None);
+        }
+
+        self.read(buf).map(Some)
+    }
+
+    /// Fetches `[offset, offset + len)` into the internal buffers ahead of time, without copying
+    /// anything out. Parts of the range which are already buffered aren't fetched again.
+    pub fn prefetch(&mut self, offset: u64, len: usize) -> std::io::Result<()> {
+        let end = offset.saturating_add(len as u64);
+
+        let mut pos = offset;
+        while pos < end {
+            let Some(&(gap_start, gap_end)) = self.missing_ranges(pos, end - pos).first() else {
+                break;
+            };
+
+            // The inner reader is exhausted, so there's nothing more to get
+            if self.read_inner(gap_start, (gap_end - gap_start) as usize)? == 0 {
+                break;
+            }
+            pos = gap_start;
+        }
+
+        Ok(())
+    }
+
+    /// Fetches exactly the parts of `[offset, offset + length)` which aren't buffered yet, with no
+    /// readahead beyond the range as [`Self::prefetch`] may do. Anything past the end of the inner
+    /// reader is skipped over.
+    pub fn fetch_range(&mut self, offset: u64, length: u64) -> std::io::Result<()> {
+        for (gap_start, gap_end) in self.missing_ranges(offset, length) {
+            if !self.fetch_span(gap_start, gap_end)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the parts of `[offset, offset + length)` which aren't buffered yet as
+    /// [`Self::fetch_range`] does, but gaps separated by no more than `max_gap` buffered bytes are
+    /// fetched together in one go, along with the bytes in between. This trades re-reading a
+    /// little data for fewer trips to the inner reader, which matters for sources where each
+    /// request is expensive.
+    pub fn fill_region_batched(
+        &mut self,
+        offset: u64,
+        length: u64,
+        max_gap: u64,
+    ) -> std::io::Result<()> {
+        let mut spans: Vec<(u64, u64)> = Vec::new();
+        for (gap_start, gap_end) in self.missing_ranges(offset, length) {
+            match spans.last_mut() {
+                Some((_, end)) if gap_start - *end <= max_gap => *end = gap_end,
+                _ => spans.push((gap_start, gap_end)),
+            }
+        }
+
+        for (start, end) in spans {
+            if !self.fetch_span(start, end)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetches all of `[start, end)` from the inner reader, in as few reads as the max read size
+    /// allows. Returns false if the inner reader ran out first.
+    fn fetch_span(&mut self, start: u64, end: u64) -> std::io::Result<bool> {
+        let mut pos = start;
+        while pos < end {
+            let len = (end - pos).min(self.max_read_size as u64) as usize;
+            let num_bytes_read = self.read_inner_into(pos, &mut vec![0; len])?;
+            // The inner reader is exhausted, so there's nothing more to get
+            if num_bytes_read == 0 {
+                return Ok(false);
+            }
+            pos += num_bytes_read as u64;
+        }
+
+        Ok(true)
+    }
+
+    /// Reads the whole of the inner reader into the internal buffers in one go, so every later
+    /// read can be served from memory. The stream length is used to size a single buffer, which
+    /// is filled in as many fetches as the max read size needs. Nothing past the read limit is
+    /// loaded, and if the rest won't fit within the memory limit, nothing is loaded at all and
+    /// [`SaturatingError::TooLargeToCache`] is returned. Returns the number of bytes loaded.
+    pub fn load_all(&mut self) -> std::io::Result<u64> {
+        let mut len = self.stream_len()?;
+        if let Some(limit) = self.read_limit() {
+            len = len.min(limit);
+        }
+        if let Some(quota) = self.read_quota {
+            if self.inner_bytes_read + len > quota {
+                return Err(SaturatingError::ReadQuotaExceeded { quota }.into());
+            }
+        }
+        // Check the length before trusting it with an allocation
+        let limit = self.cache.max_cached_bytes().unwrap_or(usize::MAX) as u64;
+        if len > limit {
+            return Err(SaturatingError::TooLargeToCache { len, limit }.into());
+        }
+        let mut buf = vec![0; len as usize];
+
+        // Fetched the same way as everything else, so retries and cancellation apply
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.fetch_uncached(filled as u64, &mut buf[filled..])? {
+                0 => break,
+                num_bytes_read => filled += num_bytes_read,
+            }
+        }
+        buf.truncate(filled);
+        self.store(0, buf);
+
+        Ok(filled as u64)
+    }
+
+    /// Breaks up the range `[offset, offset + length)` into the pieces which are held in the
+    /// internal buffers and the gaps between them, in order. Pieces may span several buffers if
+    /// they haven't been merged together.
+    pub fn covered_ranges(&self, offset: u64, length: u64) -> Vec<Segment<'_>> {
+        self.cache.covered_ranges(offset, length)
+    }
+
+    /// Checks whether all of `[offset, offset + length)` has been buffered, so reading it won't
+    /// touch the inner reader. Nothing is fetched.
+    pub fn is_cached(&self, offset: u64, length: u64) -> bool {
+        self.cache.is_cached(offset, length)
+    }
+
+    /// Returns how much of `[offset, offset + length)` has been buffered, from 0.0 for none of it
+    /// to 1.0 for all of it. An empty range counts as fully buffered.
+    pub fn coverage_fraction(&self, offset: u64, length: u64) -> f64 {
+        self.cache.coverage_fraction(offset, length)
+    }
+
+    /// Returns the gaps `[start, end)` within `[offset, offset + length)` which haven't been
+    /// buffered yet, in order. These are the ranges a [`Self::prefetch`] of the same region would
+    /// fetch.
+    pub fn missing_ranges(&self, offset: u64, length: u64) -> Vec<(u64, u64)> {
+        self.cache.missing_ranges(offset, length)
+    }
+}
+
+impl SaturatingReader<std::fs::File> {
+    /// Opens the file at `path` for reading, noting its length up front so seeking from the end
+    /// never has to touch the file.
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+
+        Ok(Self {
+            stream_len: Some(len),
+            ..Self::new(file)
+        })
+    }
+}
+
+impl From<Vec<u8>> for SaturatingReader<std::io::Cursor<Vec<u8>>> {
+    fn from(data: Vec<u8>) -> Self {
+        Self::new(std::io::Cursor::new(data))
+    }
+}
+
+impl<'a> From<&'a [u8]> for SaturatingReader<std::io::Cursor<&'a [u8]>> {
+    fn from(data: &'a [u8]) -> Self {
+        Self::new(std::io::Cursor::new(data))
+    }
+}
+
+impl<R: Read + Seek + SourceFingerprint> SaturatingReader<R> {
+    /// Creates a reader which takes note of what the inner reader looks like, so the buffers can
+    /// be dropped with [`Self::refresh_if_changed`] if it's modified.
+    pub fn with_fingerprint(inner: R) -> std::io::Result<Self> {
+        let fingerprint = inner.fingerprint()?;
+
+        Ok(Self {
+            fingerprint: Some(fingerprint),
+            ..Self::new(inner)
+        })
+    }
+
+    /// Checks whether the inner reader has changed since it was last looked at, and if so drops
+    /// all of the buffered data, which may now be stale. Returns whether anything changed. If the
+    /// reader wasn't created with [`Self::with_fingerprint`], the buffers are assumed to be stale.
+    pub fn refresh_if_changed(&mut self) -> std::io::Result<bool> {
+        let fingerprint = self.inner.fingerprint()?;
+        if self.fingerprint.replace(fingerprint) == Some(fingerprint) {
+            return Ok(false);
+        }
+
+        self.cache.clear();
+        self.invalidate_stream_len();
+        self.inner_pos = None;
+
+        Ok(true)
+    }
+}
+
+impl<R: Read + Write + Seek, S: BufferStore> SaturatingReader<R, S> {
+    /// Writes `data` to the inner reader at the given offset, updating anything buffered in that
+    /// range to match. The cursor is left where it is.
+    pub(crate) fn write_at(&mut self, offset: u64, data: &[u8]) -> std::io::Result<()> {
+        // Forget where the inner reader is while we write, in case something fails part way
+        self.inner_pos = None;
+        self.inner.seek(SeekFrom::Start(offset))?;
+        if let Err(e) = self.inner.write_all(data) {
+            // Some of it may have been written, so what's buffered there can't be trusted, and
+            // the stream may have grown
+            self.cache
+                .invalidate(offset, offset.saturating_add(data.len() as u64));
+            self.invalidate_stream_len();
+            return Err(e);
+        }
+        self.inner_pos = Some(offset + data.len() as u64);
+
+        self.cache.overwrite(offset, data);
+        // We may have made the stream longer
+        if self
+            .stream_len_hint
+            .or(self.stream_len)
+            .is_some_and(|len| offset + data.len() as u64 > len)
+        {
+            self.invalidate_stream_len();
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares what's buffered by two readers, returning the ranges `[start, end)` held by both where
+/// their data differs. Readers over the same source should never disagree, so anything returned
+/// points at stale data in one of them.
+pub fn diff_caches<A: Read + Seek, B: Read + Seek, SA: BufferStore, SB: BufferStore>(
+    a: &SaturatingReader<A, SA>,
+    b: &SaturatingReader<B, SB>,
+) -> Vec<(u64, u64)> {
+    a.cache.diff(&b.cache)
+}
+
+/// A short summary of what's buffered, leaving out the data itself.
+impl<R: Read + Seek, S: BufferStore> std::fmt::Display for SaturatingReader<R, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SaturatingReader at {}: {} buffers holding {} bytes",
+            self.cursor_pos,
+            self.cache.buffers.len(),
+            self.cache.cached_bytes()
+        )?;
+        for (i, (start, end)) in self.cached_ranges().enumerate() {
+            let sep = if i == 0 { " in " } else { ", " };
+            write!(f, "{sep}[{start}, {end})")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Seek + Read, S: BufferStore> Read for SaturatingReader<R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let num_bytes_read = self.read_at(self.cursor_pos, buf)?;
+        self.cursor_pos += num_bytes_read as u64;
+        self.slide_window();
+
+        Ok(num_bytes_read)
+    }
+
+    /// If there's anything buffered at the cursor, copies as much of it as fits straight out of
+    /// the internal buffers, so the destination doesn't need initialising first. This may be less
+    /// than was asked for. Otherwise the destination is initialised and read into as usual.
+    #[cfg(feature = "nightly")]
+    fn read_buf(&mut self, mut cursor: std::io::BorrowedCursor<'_>) -> std::io::Result<()> {
+        let offset = self.cursor_pos;
+        #[cfg(feature = "checksum")]
+        self.cache
+            .drop_corrupt(offset, offset.saturating_add(cursor.capacity() as u64));
+
+        let capacity = self.clip_to_limit(offset, cursor.capacity());
+        let len = match self.cache.get_prefix(offset) {
+            Some(data) if capacity > 0 => {
+                let len = data.len().min(capacity);
+                cursor.append(&data[..len]);
+                len
+            }
+            _ => {
+                let num_bytes_read = self.read(cursor.ensure_init())?;
+                cursor.advance_checked(num_bytes_read);
+                return Ok(());
+            }
+        };
+
+        self.stats.hits += 1;
+        self.stats.bytes_served_from_cache += len as u64;
+        self.cache.events.emit(CacheEvent::Hit {
+            offset,
+            len: len as u64,
+        });
+        self.cache.touch(offset, offset + len as u64);
+        self.cursor_pos += len as u64;
+        self.slide_window();
+
+        Ok(())
+    }
+
+    /// Fills the buffers in order, as though they were one contiguous buffer. Anything already
+    /// buffered is copied straight across, and only what's missing is fetched.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        let offset = self.cursor_pos;
+        let len = bufs
+            .iter()
+            .fold(0usize, |len, buf| len.saturating_add(buf.len()));
+        let len = self.clip_to_limit(offset, len);
+        if len == 0 {
+            return Ok(0);
+        }
+        #[cfg(feature = "checksum")]
+        self.cache
+            .drop_corrupt(offset, offset.saturating_add(len as u64));
+
+        // How far we've got, as the slice being filled and how far into it
+        let (mut i, mut j, mut filled) = (0, 0, 0);
+        let mut reused = None;
+        let mut fetched = false;
+        loop {
+            // Copy across everything buffered from here, spilling from one slice into the next
+            while filled < len {
+                let Some(data) = self.cache.get_prefix_or_hole(offset + filled as u64) else {
+                    break;
+                };
+                let mut data = &data[..data.len().min(len - filled)];
+                while !data.is_empty() {
+                    let buf = &mut bufs[i][j..];
+                    let n = buf.len().min(data.len());
+                    buf[..n].copy_from_slice(&data[..n]);
+                    data = &data[n..];
+                    (j, filled) = (j + n, filled + n);
+                    if j == bufs[i].len() {
+                        (i, j) = (i + 1, 0);
+                    }
+                }
+            }
+            // Only what was there before we fetched anything counts as served from the cache
+            if reused.is_none() {
+                reused = Some(filled as u64);
+                if filled > 0 {
+                    self.cache.events.emit(CacheEvent::Hit {
+                        offset,
+                        len: filled as u64,
+                    });
+                }
+            }
+            if filled == len {
+                break;
+            }
+
+            // Then fetch the rest of the slice we're on, which reads ahead into the next ones
+            fetched = true;
+            let buf = &mut bufs[i][j..];
+            let end = buf.len().min(len - filled);
+            let num_bytes_read = match self.read_at(offset + filled as u64, &mut buf[..end]) {
+                Ok(num_bytes_read) => num_bytes_read,
+                Err(e) if filled == 0 => return Err(e),
+                // We've already handed over some bytes, so report those instead. The error will
+                // come up again on the next read.
+                Err(_) => break,
+            };
+            (j, filled) = (j + num_bytes_read, filled + num_bytes_read);
+            if j == bufs[i].len() {
+                (i, j) = (i + 1, 0);
+            }
+
+            // A short read means the inner reader is exhausted
+            if num_bytes_read < end {
+                break;
+            }
+        }
+
+        let reused = reused.unwrap_or_default();
+        self.stats.bytes_served_from_cache += reused;
+        if fetched {
+            self.stats.bytes_served_from_partial_hits += reused;
+        } else {
+            self.stats.hits += 1;
+        }
+        self.cache.touch(offset, offset + reused);
+        self.cursor_pos += filled as u64;
+        self.slide_window();
+
+        Ok(filled)
+    }
+}
+
+impl<R: Read + Seek, S: BufferStore> BufRead for SaturatingReader<R, S> {
+    /// Returns all of the contiguous buffered data from the cursor onwards, fetching from the inner
+    /// reader if nothing is buffered there yet. Holes are returned as zeros, a piece at a time.
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        #[cfg(feature = "checksum")]
+        self.cache
+            .drop_corrupt(self.cursor_pos, self.cursor_pos.saturating_add(1));
+
+        if self.cache.get_prefix_or_hole(self.cursor_pos).is_none() {
+            self.read_inner(self.cursor_pos, self.bufread_size)?;
+        }
+
+        // If there's still nothing then we're at the end of the inner reader
+        let data = self
+            .cache
+            .get_prefix_or_hole(self.cursor_pos)
+            .unwrap_or_default();
+        let available = match self.read_limit() {
+            Some(limit) => limit.saturating_sub(self.cursor_pos).min(data.len() as u64),
+            None => data.len() as u64,
+        };
+
+        Ok(&data[..available as usize])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.cache
+            .touch(self.cursor_pos, self.cursor_pos + amt as u64);
+        self.cursor_pos += amt as u64;
+        self.slide_window();
+    }
+}
+
+impl<R: Read + Seek, S: BufferStore> Seek for SaturatingReader<R, S> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match pos {
+            // For start/current, don't seek the underlying reader. It will be handled in read() if
+            // needed.
+            SeekFrom::Start(p) => self.cursor_pos = p,
+            SeekFrom::Current(p) => {
+                self.cursor_pos = self.cursor_pos.checked_add_signed(p).ok_or(
+                    SaturatingError::SeekUnderflow {
+                        position: self.cursor_pos,
+                        offset: p,
+                    },
+                )?;
+            }
+            // Work out the length once, then further end seeks don't touch the inner reader
+            SeekFrom::End(p) => {
+                let len = self.stream_len()?;
+                self.cursor_pos =
+                    len.checked_add_signed(p)
+                        .ok_or(SaturatingError::SeekUnderflow {
+                            position: len,
+                            offset: p,
+                        })?;
+            }
+        };
+
+        Ok(self.cursor_pos)
+    }
+
+    fn rewind(&mut self) -> std::io::Result<()> {
+        self.cursor_pos = 0;
+
+        Ok(())
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.cursor_pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, Cursor, ErrorKind, IoSliceMut, Read, Seek, SeekFrom, Write},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use super::{
+        diff_caches, import_coverage, MergePolicy, RetryPolicy, SaturatingReader,
+        SaturatingReaderBuilder, DEFAULT_MAX_READ_SIZE,
+    };
+    use crate::{
+        buffer::{Buffer, BufferStore},
+        error::SaturatingError,
+        stats::{CacheEvent, CacheStats},
+    };
+
+    /// Keeps buffer data on the heap like the default store, counting how many allocations are
+    /// made and freed.
+    #[derive(Debug, Clone, Default)]
+    struct CountingStore {
+        allocs: Arc<AtomicUsize>,
+        frees: Arc<AtomicUsize>,
+    }
+
+    impl BufferStore for CountingStore {
+        type Data = Vec<u8>;
+
+        fn alloc(&self, data: &[u8]) -> Vec<u8> {
+            self.allocs.fetch_add(1, Ordering::Relaxed);
+            data.to_vec()
+        }
+
+        fn alloc_vec(&self, data: Vec<u8>) -> Vec<u8> {
+            self.allocs.fetch_add(1, Ordering::Relaxed);
+            data
+        }
+
+        fn get<'a>(&'a self, data: &'a Vec<u8>) -> &'a [u8] {
+            data
+        }
+
+        fn free(&self, _data: Vec<u8>) {
+            self.frees.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn append(&self, data: &mut Vec<u8>, bytes: &[u8]) {
+            data.extend_from_slice(bytes);
+        }
+    }
+
+    /// Wraps a reader, counting how many times it gets read from and seeked.
+    #[derive(Clone)]
+    struct CountingReader<R> {
+        inner: R,
+        reads: usize,
+        seeks: usize,
+        end_seeks: usize,
+    }
+
+    impl<R> CountingReader<R> {
+        fn new(inner: R) -> Self {
+            Self {
+                inner,
+                reads: 0,
+                seeks: 0,
+                end_seeks: 0,
+            }
+        }
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.seeks += 1;
+            if matches!(pos, SeekFrom::End(_)) {
+                self.end_seeks += 1;
+            }
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test1() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::new(reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).unwrap();
+        println!("{:?}", bufreader);
+        bufreader.read_exact(&mut buf).unwrap();
+        println!("{:?}", bufreader);
+
+        let mut buf = vec![];
+        let n = bufreader.read_to_end(&mut buf).unwrap();
+        println!("{:?} {:?}", n, bufreader);
+    }
+
+    #[test]
+    fn test2() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).unwrap();
+
+        // Repeated
+        bufreader.seek(SeekFrom::Start(0)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+
+        assert_eq!(buf.as_slice(), (0..64).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.cache.buffers.len(), 1);
+        assert_eq!(bufreader.cache.buffers[0].range(), (0, 64));
+        println!("{:?}", bufreader.cache.buffers);
+
+        // Partial overlap
+        bufreader.seek(SeekFrom::Start(32)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+
+        assert_eq!(buf.as_slice(), (32..96).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.cache.buffers.len(), 1);
+        assert_eq!(bufreader.cache.buffers[0].range(), (0, 96));
+        println!("{:?}", bufreader.cache.buffers);
 
         // Disjoint
         bufreader.seek(SeekFrom::Start(128)).unwrap();
@@ -175,9 +1829,2368 @@ mod tests {
             buf.as_slice(),
             (128..128 + 64).collect::<Vec<_>>().as_slice()
         );
-        assert_eq!(bufreader.buffers.len(), 2);
-        assert_eq!(bufreader.buffers[0].range(), (0, 96));
-        assert_eq!(bufreader.buffers[1].range(), (128, 128 + 64));
-        println!("{:?}", bufreader.buffers);
+        assert_eq!(bufreader.cache.buffers.len(), 2);
+        assert_eq!(bufreader.cache.buffers[0].range(), (0, 96));
+        assert_eq!(bufreader.cache.buffers[1].range(), (128, 128 + 64));
+        println!("{:?}", bufreader.cache.buffers);
+    }
+
+    #[test]
+    fn test_read_past_end() {
+        let reader = Cursor::new(vec![0u8; 10]);
+        let mut bufreader = SaturatingReader::new(reader);
+
+        let mut buf = [0; 64];
+        let n = bufreader.read(&mut buf).unwrap();
+        assert_eq!(n, 10);
+
+        // Subsequent reads are at EOF
+        let n = bufreader.read(&mut buf).unwrap();
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn test_read_at_eof_leaves_no_empty_buffers() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+
+        let mut buf = [0; 16];
+        assert_eq!(bufreader.read_at(256, &mut buf).unwrap(), 0);
+        assert_eq!(bufreader.read_at(1000, &mut buf).unwrap(), 0);
+        bufreader.seek(SeekFrom::Start(256)).unwrap();
+        assert!(bufreader.fill_buf().unwrap().is_empty());
+        assert_eq!(bufreader.cached_ranges().count(), 0);
+
+        // Data touching where the empty reads were still reads back fine
+        assert_eq!(bufreader.read_at(240, &mut buf).unwrap(), 16);
+        assert_eq!(buf.as_slice(), (240..=255).collect::<Vec<_>>().as_slice());
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(240, 256)]
+        );
+    }
+
+    #[test]
+    fn test_read_final_partial_chunk() {
+        // 100 bytes doesn't divide evenly into 32 byte inner reads
+        let reader = Cursor::new((0..100).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(32, reader);
+
+        let mut buf = [0; 32];
+        for i in 0..3 {
+            bufreader.read_exact(&mut buf).unwrap();
+            assert_eq!(
+                buf.as_slice(),
+                (i * 32..(i + 1) * 32).collect::<Vec<_>>().as_slice()
+            );
+        }
+
+        // Only the last 4 bytes are left
+        let mut buf = [0; 64];
+        let n = bufreader.read(&mut buf).unwrap();
+        assert_eq!(n, 4);
+        assert_eq!(&buf[..n], &[96, 97, 98, 99]);
+
+        // Re-reading the tail gives a short read straight from the cache
+        bufreader.seek(SeekFrom::Start(90)).unwrap();
+        let n = bufreader.read(&mut buf).unwrap();
+        assert_eq!(n, 10);
+        assert_eq!(&buf[..n], (90..100).collect::<Vec<_>>().as_slice());
+    }
+
+    #[test]
+    fn test_read_partial_overlap() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+
+        // Cache [0, 32) and [64, 96)
+        let mut buf = [0; 32];
+        bufreader.read_exact(&mut buf).unwrap();
+        bufreader.seek(SeekFrom::Start(64)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(bufreader.cache.buffers.len(), 2);
+
+        // Straddle both buffers and the gap between them
+        let mut buf = [0; 64];
+        bufreader.seek(SeekFrom::Start(16)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (16..80).collect::<Vec<_>>().as_slice());
+
+        // Only the gap was fetched, which joins everything into one buffer
+        assert_eq!(bufreader.cache.buffers.len(), 1);
+        assert_eq!(bufreader.cache.buffers[0].range(), (0, 96));
+    }
+
+    #[test]
+    fn test_read_across_unmerged_buffers() {
+        // The inner reader is all zeros, so anything else must have come from the cache
+        let reader = Cursor::new(vec![0; 256]);
+        let mut bufreader = SaturatingReader::new(reader);
+        bufreader
+            .cache
+            .buffers
+            .push(Buffer::from_slice(0, &(0..50).collect::<Vec<_>>()));
+        bufreader
+            .cache
+            .buffers
+            .push(Buffer::from_slice(50, &(50..100).collect::<Vec<_>>()));
+
+        let mut buf = [0; 100];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..100).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.cache.buffers.len(), 2);
+    }
+
+    #[test]
+    fn test_coalesce_adjacent() {
+        let reader = Cursor::new(vec![0; 256]);
+        let mut bufreader = SaturatingReader::new(reader);
+        for start in (0..100).step_by(20) {
+            bufreader.cache.buffers.push(Buffer::from_slice(
+                start,
+                &(start as u8..start as u8 + 20).collect::<Vec<_>>(),
+            ));
+        }
+        bufreader
+            .cache
+            .buffers
+            .push(Buffer::from_slice(150, &[1; 10]));
+
+        bufreader.coalesce_adjacent();
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 100), (150, 160)]
+        );
+        assert_eq!(
+            bufreader.cache.buffers[0].data(),
+            (0..100).collect::<Vec<_>>().as_slice()
+        );
+
+        // Nothing left to do the second time round
+        bufreader.coalesce_adjacent();
+        assert_eq!(bufreader.cache.buffers.len(), 2);
+    }
+
+    #[test]
+    fn test_on_event() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity_and_limit(16, 32, reader);
+        let events = Arc::new(Mutex::new(vec![]));
+        let recorded = events.clone();
+        bufreader.on_event(move |event| recorded.lock().unwrap().push(event));
+
+        let mut buf = [0; 16];
+        // Cold, then warm
+        bufreader.read_at(0, &mut buf).unwrap();
+        bufreader.read_at(0, &mut buf).unwrap();
+        bufreader.read_at(100, &mut buf).unwrap();
+        // Over the limit, so the oldest goes
+        bufreader.read_at(200, &mut buf).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                CacheEvent::Fetch { offset: 0, len: 16 },
+                CacheEvent::Hit { offset: 0, len: 16 },
+                CacheEvent::Fetch {
+                    offset: 100,
+                    len: 16
+                },
+                CacheEvent::Fetch {
+                    offset: 200,
+                    len: 16
+                },
+                CacheEvent::Evict { offset: 0, len: 16 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_policy() {
+        let layout = |merge_policy| {
+            let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+            let mut bufreader = SaturatingReaderBuilder::new()
+                .read_size(16)
+                .merge_policy(merge_policy)
+                .build(reader);
+
+            let mut buf = [0; 16];
+            for offset in [0, 16, 40, 100] {
+                bufreader.read_exact_at(offset, &mut buf).unwrap();
+                assert_eq!(
+                    buf.as_slice(),
+                    (offset as u8..offset as u8 + 16)
+                        .collect::<Vec<_>>()
+                        .as_slice()
+                );
+            }
+            // Reading back across the whole lot gives the same data whatever the layout
+            let mut buf = [0; 56];
+            bufreader.read_exact_at(0, &mut buf).unwrap();
+            assert_eq!(buf.as_slice(), (0..56).collect::<Vec<_>>().as_slice());
+
+            bufreader.cached_ranges().collect::<Vec<_>>()
+        };
+
+        assert_eq!(layout(MergePolicy::Always), vec![(0, 56), (100, 116)]);
+        assert_eq!(
+            layout(MergePolicy::Never),
+            vec![(0, 16), (16, 32), (32, 40), (40, 56), (100, 116)]
+        );
+        // Only the gap of 8 bytes is small enough to bridge
+        assert_eq!(
+            layout(MergePolicy::BridgeGaps(8)),
+            vec![(0, 56), (100, 116)]
+        );
+        assert_eq!(layout(MergePolicy::BridgeGaps(50)), vec![(0, 116)]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let ranges = [(0, 16), (50, 10), (16, 16), (8, 30)];
+        let normalized = |ranges: &mut dyn Iterator<Item = &(u64, u64)>| {
+            let mut bufreader = SaturatingReaderBuilder::new()
+                .read_size(4)
+                .merge_policy(MergePolicy::Never)
+                .build(Cursor::new((0..=255).collect::<Vec<_>>()));
+            for &(offset, len) in ranges {
+                bufreader.fetch_range(offset, len).unwrap();
+            }
+            bufreader.normalize();
+
+            bufreader.cache.buffers
+        };
+
+        let forwards = normalized(&mut ranges.iter());
+        let backwards = normalized(&mut ranges.iter().rev());
+        assert_eq!(
+            forwards.iter().map(|b| b.range()).collect::<Vec<_>>(),
+            vec![(0, 38), (50, 60)]
+        );
+        assert_eq!(
+            forwards.iter().map(|b| b.range()).collect::<Vec<_>>(),
+            backwards.iter().map(|b| b.range()).collect::<Vec<_>>()
+        );
+        for (a, b) in forwards.iter().zip(&backwards) {
+            assert_eq!(a.data(), b.data());
+        }
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(vec![1; 256]));
+        for offset in (0..256).step_by(32) {
+            bufreader.fetch_range(offset, 16).unwrap();
+        }
+        bufreader.drop_range(0, 200);
+        assert!(bufreader.cache.buffers.capacity() > bufreader.cache.buffers.len());
+
+        bufreader.shrink_to_fit();
+        assert_eq!(
+            bufreader.cache.buffers.capacity(),
+            bufreader.cache.buffers.len()
+        );
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(200, 208), (224, 240)]
+        );
+    }
+
+    #[test]
+    fn test_read_large_miss_direct() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+
+        // A gap bigger than the read size goes straight into the caller's buffer, in one read
+        let mut buf = [0; 200];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..200).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, 1);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 200)]
+        );
+        assert_eq!(bufreader.stats().bytes_served_from_cache, 0);
+
+        // Straddling the cached data only fetches what's missing
+        bufreader.seek(SeekFrom::Start(150)).unwrap();
+        let mut buf = [0; 100];
+        assert_eq!(bufreader.read(&mut buf).unwrap(), 100);
+        assert_eq!(buf.as_slice(), (150..250).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, 2);
+        assert_eq!(bufreader.stats().bytes_served_from_cache, 50);
+        assert_eq!(bufreader.stats().bytes_read_from_inner, 250);
+    }
+
+    #[test]
+    fn test_max_read_size() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.set_max_read_size(100);
+
+        // Larger requests are fetched in chunks
+        let mut buf = [0; 250];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..250).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, 3);
+
+        // An enormous prefetch only holds on to what's actually there
+        let mut bufreader = SaturatingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        bufreader.set_max_read_size(1024);
+        bufreader.prefetch(0, usize::MAX).unwrap();
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 256)]
+        );
+
+        // As does an enormous read, which comes up short
+        let mut buf = vec![0; 64 * 1024 * 1024];
+        assert_eq!(bufreader.read(&mut buf).unwrap(), 256);
+        assert_eq!(bufreader.cached_bytes(), 256);
+    }
+
+    #[test]
+    fn test_sequential_misses_dont_seek() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+
+        // Only the first miss has to find out where the inner reader is
+        let mut buf = [0; 16];
+        for i in 0..8 {
+            bufreader.read_exact(&mut buf).unwrap();
+            assert_eq!(
+                buf.as_slice(),
+                (i * 16..(i + 1) * 16).collect::<Vec<_>>().as_slice()
+            );
+        }
+        assert_eq!(bufreader.inner.reads, 8);
+        assert_eq!(bufreader.inner.seeks, 1);
+
+        // Jumping about needs a seek per miss
+        bufreader.seek(SeekFrom::Start(200)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (200..216).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.seeks, 2);
+
+        // And the position is kept up to date after finding the length, which leaves the inner
+        // reader at the end
+        bufreader.seek(SeekFrom::End(-16)).unwrap();
+        assert_eq!(bufreader.inner.seeks, 3);
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (240..=255).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.seeks, 4);
+    }
+
+    #[test]
+    fn test_seek_underflow() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::new(reader);
+        bufreader.seek(SeekFrom::Start(5)).unwrap();
+
+        let e = bufreader.seek(SeekFrom::Current(-10)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert_eq!(
+            e.get_ref()
+                .and_then(|e| e.downcast_ref::<SaturatingError>()),
+            Some(&SaturatingError::SeekUnderflow {
+                position: 5,
+                offset: -10
+            })
+        );
+
+        // The cursor is left where it was
+        assert_eq!(bufreader.stream_position().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_invalidate_stream_len() {
+        let reader = CountingReader::new(Cursor::new((0..100).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::new(reader);
+
+        // The length is only looked up the first time
+        assert_eq!(bufreader.seek(SeekFrom::End(0)).unwrap(), 100);
+        assert_eq!(bufreader.seek(SeekFrom::End(-10)).unwrap(), 90);
+        assert_eq!(bufreader.seek(SeekFrom::End(-50)).unwrap(), 50);
+        assert_eq!(bufreader.get_ref().end_seeks, 1);
+
+        // Until the source grows and we're told to forget it
+        bufreader.get_mut().inner.get_mut().extend(100..150);
+        assert_eq!(bufreader.seek(SeekFrom::End(0)).unwrap(), 100);
+        bufreader.invalidate_stream_len();
+        assert_eq!(bufreader.seek(SeekFrom::End(-10)).unwrap(), 140);
+        assert_eq!(bufreader.seek(SeekFrom::End(0)).unwrap(), 150);
+        assert_eq!(bufreader.get_ref().end_seeks, 2);
+
+        let mut buf = [0; 10];
+        bufreader.seek(SeekFrom::Start(140)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (140..150).collect::<Vec<_>>().as_slice());
+    }
+
+    #[test]
+    fn test_repeated_seek_from_end() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::new(reader);
+
+        let mut first = [0; 10];
+        assert_eq!(bufreader.seek(SeekFrom::End(-10)).unwrap(), 246);
+        bufreader.read_exact(&mut first).unwrap();
+        let mut second = [0; 10];
+        assert_eq!(bufreader.seek(SeekFrom::End(-10)).unwrap(), 246);
+        bufreader.read_exact(&mut second).unwrap();
+
+        assert_eq!(first.as_slice(), (246..=255).collect::<Vec<_>>().as_slice());
+        assert_eq!(first, second);
+        assert_eq!(bufreader.inner.reads, 1);
+        // One seek to find the length and one to get back to the data
+        assert_eq!(bufreader.inner.seeks, 2);
+
+        assert_eq!(
+            bufreader.seek(SeekFrom::End(-300)).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_position() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::new(reader);
+        assert_eq!(bufreader.position(), 0);
+
+        let mut buf = [0; 10];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(bufreader.position(), 10);
+
+        bufreader.set_position(100);
+        assert_eq!(bufreader.position(), 100);
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (100..110).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.position(), 110);
+
+        // Past the end is fine, there's just nothing to read
+        bufreader.set_position(1000);
+        assert_eq!(bufreader.read(&mut buf).unwrap(), 0);
+        assert_eq!(bufreader.position(), 1000);
+    }
+
+    #[test]
+    fn test_seek_back_current() {
+        for merge_policy in [MergePolicy::Always, MergePolicy::Never] {
+            let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+            let mut bufreader = SaturatingReaderBuilder::new()
+                .read_size(16)
+                .merge_policy(merge_policy)
+                .build(reader);
+            let mut buf = [0; 16];
+            bufreader.read_exact(&mut buf).unwrap();
+            bufreader.read_exact(&mut buf).unwrap();
+            let reads = bufreader.inner.reads;
+
+            // Exactly on the boundary between the two reads
+            assert_eq!(bufreader.seek(SeekFrom::Current(-16)).unwrap(), 16);
+            bufreader.read_exact(&mut buf[..8]).unwrap();
+            assert_eq!(&buf[..8], (16..24).collect::<Vec<_>>().as_slice());
+
+            // Part way into the first, reading across into the second
+            assert_eq!(bufreader.seek(SeekFrom::Current(-20)).unwrap(), 4);
+            bufreader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), (4..20).collect::<Vec<_>>().as_slice());
+            assert_eq!(bufreader.inner.reads, reads);
+
+            // Before everything that's buffered
+            bufreader.drop_range(0, 10);
+            assert_eq!(bufreader.seek(SeekFrom::Current(-15)).unwrap(), 5);
+            bufreader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), (5..21).collect::<Vec<_>>().as_slice());
+        }
+    }
+
+    #[test]
+    fn test_stream_position() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.seek(SeekFrom::Start(50)).unwrap();
+        bufreader.seek(SeekFrom::Current(-20)).unwrap();
+        assert_eq!(bufreader.stream_position().unwrap(), 30);
+
+        bufreader.read_exact(&mut [0; 10]).unwrap();
+        bufreader.seek(SeekFrom::Current(5)).unwrap();
+        let (reads, seeks) = (bufreader.inner.reads, bufreader.inner.seeks);
+        assert_eq!(bufreader.stream_position().unwrap(), 45);
+        assert_eq!(bufreader.inner.reads, reads);
+        assert_eq!(bufreader.inner.seeks, seeks);
+    }
+
+    #[test]
+    fn test_open() {
+        let path =
+            std::env::temp_dir().join(format!("saturating_reader_open_{}", std::process::id()));
+        std::fs::write(&path, (0..=255).collect::<Vec<_>>()).unwrap();
+
+        let mut bufreader = SaturatingReader::open(&path).unwrap();
+        assert_eq!(bufreader.stream_len().unwrap(), 256);
+        assert_eq!(bufreader.seek(SeekFrom::End(-6)).unwrap(), 250);
+        // Nothing has needed the file to move yet
+        assert_eq!(bufreader.inner_pos, None);
+
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, (250..=255).collect::<Vec<_>>());
+
+        assert!(SaturatingReader::open(path.with_extension("missing")).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_growing() {
+        let path =
+            std::env::temp_dir().join(format!("saturating_reader_growing_{}", std::process::id()));
+        std::fs::write(&path, [1; 100]).unwrap();
+
+        let mut bufreader = SaturatingReader::open(&path).unwrap();
+        let mut buf = vec![];
+        assert_eq!(bufreader.read_to_end(&mut buf).unwrap(), 100);
+
+        // The length found up front doesn't stop reads once the file has grown past it
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[2; 50])
+            .unwrap();
+        buf.clear();
+        assert_eq!(bufreader.read_to_end(&mut buf).unwrap(), 50);
+        assert_eq!(buf, [2; 50]);
+        assert_eq!(bufreader.read_range_exact(90, 20).unwrap().len(), 20);
+
+        // Whereas a hint does
+        bufreader.set_stream_len_hint(120);
+        bufreader.rewind().unwrap();
+        buf.clear();
+        assert_eq!(bufreader.read_to_end(&mut buf).unwrap(), 120);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_into_from_parts() {
+        let data = (0..=255).collect::<Vec<_>>();
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(data.clone()));
+        let mut buf = [0; 32];
+        bufreader.read_exact(&mut buf).unwrap();
+        bufreader.seek(SeekFrom::Start(100)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+
+        let (_, buffers, cursor_pos) = bufreader.into_parts();
+        assert_eq!(cursor_pos, 132);
+
+        // The new reader picks up where the old one left off, with everything still buffered
+        let reader = CountingReader::new(Cursor::new(data));
+        let mut bufreader = SaturatingReader::from_parts(reader, buffers, cursor_pos, 16);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 32), (100, 132)]
+        );
+        bufreader.seek(SeekFrom::Start(100)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (100..132).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, 0);
+
+        // Buffers out of order or overlapping are tidied up
+        let buffers = vec![
+            Buffer::from_slice(50, &[2; 50]),
+            Buffer::from_slice(0, &[1; 60]),
+            Buffer::from_slice(200, &[]),
+        ];
+        let bufreader = SaturatingReader::from_parts(Cursor::new(vec![]), buffers, 0, 16);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 100)]
+        );
+    }
+
+    #[test]
+    fn test_with_buffers() {
+        let buffers = vec![
+            Buffer::from_slice(120, &[3; 10]),
+            Buffer::from_slice(10, &[1; 20]),
+            Buffer::from_slice(100, &[2; 20]),
+            Buffer::from_slice(20, &[2; 20]),
+            Buffer::from_slice(35, &[2; 5]),
+        ];
+        let reader = CountingReader::new(Cursor::new(vec![0; 256]));
+        let mut bufreader = SaturatingReader::with_buffers(reader, buffers);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(10, 40), (100, 130)]
+        );
+
+        let mut buf = [0; 30];
+        bufreader.read_exact_at(100, &mut buf).unwrap();
+        assert_eq!(&buf[..20], &[2; 20]);
+        assert_eq!(&buf[20..], &[3; 10]);
+        assert_eq!(bufreader.inner.reads, 0);
+    }
+
+    #[test]
+    fn test_get_ref_mut() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+
+        let mut buf = [0; 10];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(bufreader.position(), 10);
+        assert_eq!(bufreader.get_ref().inner.position(), 16);
+
+        // Moving the inner reader behind our back doesn't throw off later reads
+        bufreader.get_mut().inner.set_position(200);
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (10..20).collect::<Vec<_>>().as_slice());
+    }
+
+    #[test]
+    fn test_read_quota() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(16)
+            .read_quota(48)
+            .build(reader);
+
+        let mut buf = [0; 16];
+        for offset in [0, 100, 200] {
+            bufreader.read_exact_at(offset, &mut buf).unwrap();
+        }
+        let err = bufreader.read_at(50, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::QuotaExceeded);
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<SaturatingError>(),
+            Some(&SaturatingError::ReadQuotaExceeded { quota: 48 })
+        );
+        assert_eq!(bufreader.inner.reads, 3);
+
+        // What's already buffered can still be read
+        bufreader.read_exact_at(100, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (100..116).collect::<Vec<_>>().as_slice());
+
+        // Fetches are cut short to fit what's left
+        let mut bufreader = SaturatingReader::with_read_quota(20, Cursor::new(vec![0; 256]));
+        assert_eq!(bufreader.read(&mut [0; 100]).unwrap(), 20);
+        assert!(bufreader.read(&mut [0; 100]).is_err());
+
+        // Fetches of nothing don't count as going over
+        bufreader.set_stream_len_hint(256);
+        bufreader.prefetch(50, 0).unwrap();
+        bufreader.prefetch(300, 10).unwrap();
+        assert_eq!(bufreader.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_window() {
+        let data = (0..64 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+        let reader = CountingReader::new(Cursor::new(data.clone()));
+        let mut bufreader = SaturatingReader::with_window(16 * 1024, reader);
+
+        let mut buf = [0; 100];
+        let mut pos = 0;
+        loop {
+            let num_bytes_read = bufreader.read(&mut buf).unwrap();
+            if num_bytes_read == 0 {
+                break;
+            }
+            assert_eq!(&buf[..num_bytes_read], &data[pos..pos + num_bytes_read]);
+            pos += num_bytes_read;
+            assert!(bufreader.cached_bytes() <= 2 * 16 * 1024);
+        }
+        assert_eq!(pos, data.len());
+
+        // Just behind the cursor is still buffered
+        let reads = bufreader.inner.reads;
+        bufreader.seek(SeekFrom::Current(-1000)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), &data[data.len() - 1000..data.len() - 900]);
+        assert_eq!(bufreader.inner.reads, reads);
+
+        // But the start of the file isn't
+        assert_eq!(bufreader.missing_ranges(0, 1000), vec![(0, 1000)]);
+    }
+
+    #[test]
+    fn test_window_pinned() {
+        let data = (0..64 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+        let reader = CountingReader::new(Cursor::new(data.clone()));
+        let mut bufreader = SaturatingReader::with_window(16 * 1024, reader);
+        bufreader.pin(0, 100).unwrap();
+
+        // The pinned header stays put as the window slides away from it
+        bufreader.seek(SeekFrom::Start(48 * 1024)).unwrap();
+        bufreader.read_exact(&mut [0; 100]).unwrap();
+        assert!(bufreader.missing_ranges(0, 100).is_empty());
+        assert_eq!(bufreader.missing_ranges(100, 1000), vec![(100, 1100)]);
+    }
+
+    #[test]
+    fn test_fetch_range() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(64, reader);
+        bufreader.fetch_range(0, 10).unwrap();
+        bufreader.fetch_range(50, 10).unwrap();
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 10), (50, 60)]
+        );
+
+        // Only the holes are fetched, and nothing beyond the range
+        bufreader.fetch_range(5, 95).unwrap();
+        assert_eq!(bufreader.inner.reads, 4);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 100)]
+        );
+
+        // Past the end is clipped without an error
+        bufreader.fetch_range(200, 1000).unwrap();
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 100), (200, 256)]
+        );
+        let mut buf = [0; 56];
+        bufreader.read_at(200, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (200..=255).collect::<Vec<_>>().as_slice());
+    }
+
+    #[test]
+    fn test_load_all() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.fetch_range(100, 10).unwrap();
+        let reads = bufreader.inner.reads;
+
+        assert_eq!(bufreader.load_all().unwrap(), 256);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 256)]
+        );
+        assert_eq!(bufreader.inner.reads, reads + 1);
+
+        // Everything is served from memory from now on
+        let mut buf = [0; 256];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..=255).collect::<Vec<_>>().as_slice());
+        bufreader.seek(SeekFrom::Start(200)).unwrap();
+        bufreader.read_exact(&mut [0; 50]).unwrap();
+        assert_eq!(bufreader.inner.reads, reads + 1);
+    }
+
+    #[test]
+    fn test_load_all_limits() {
+        // Nothing past the logical end is loaded
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::new(reader);
+        bufreader.set_logical_eof(100);
+        assert_eq!(bufreader.load_all().unwrap(), 100);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 100)]
+        );
+
+        // A stream too big for the memory limit isn't even allocated for
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity_and_limit(16, 200, reader);
+        let err = bufreader.load_all().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<SaturatingError>(),
+            Some(&SaturatingError::TooLargeToCache {
+                len: 256,
+                limit: 200
+            })
+        );
+        assert_eq!(bufreader.inner.reads, 0);
+        assert_eq!(bufreader.cached_bytes(), 0);
+
+        // The max read size splits it up, but it still ends up in one buffer
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .max_read_size(64)
+            .build(reader);
+        assert_eq!(bufreader.load_all().unwrap(), 256);
+        assert_eq!(bufreader.inner.reads, 4);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 256)]
+        );
+    }
+
+    #[test]
+    fn test_read_vectored() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+        bufreader.fetch_range(0, 100).unwrap();
+        bufreader.reset_stats();
+        let reads = bufreader.inner.reads;
+
+        // Served entirely from the cache, as a single hit
+        let (mut a, mut b, mut c) = ([0; 10], [0; 20], [0; 30]);
+        let mut bufs = [
+            IoSliceMut::new(&mut a),
+            IoSliceMut::new(&mut []),
+            IoSliceMut::new(&mut b),
+            IoSliceMut::new(&mut c),
+        ];
+        assert_eq!(bufreader.read_vectored(&mut bufs).unwrap(), 60);
+        assert_eq!(a.as_slice(), (0..10).collect::<Vec<_>>().as_slice());
+        assert_eq!(b.as_slice(), (10..30).collect::<Vec<_>>().as_slice());
+        assert_eq!(c.as_slice(), (30..60).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, reads);
+        assert_eq!(bufreader.position(), 60);
+        assert_eq!(bufreader.stats().hits, 1);
+        assert_eq!(bufreader.stats().bytes_served_from_cache, 60);
+
+        // Only the uncached tail is fetched, and the end of the stream cuts it short
+        bufreader.set_position(90);
+        let (mut a, mut b, mut c) = ([0; 100], [0; 100], [0; 100]);
+        let mut bufs = [
+            IoSliceMut::new(&mut a),
+            IoSliceMut::new(&mut b),
+            IoSliceMut::new(&mut c),
+        ];
+        assert_eq!(bufreader.read_vectored(&mut bufs).unwrap(), 166);
+        assert_eq!(a.as_slice(), (90..190).collect::<Vec<_>>().as_slice());
+        assert_eq!(&b[..66], (190..=255).collect::<Vec<_>>().as_slice());
+        // One read for each slice's gap, then one more to find the end
+        assert_eq!(bufreader.inner.reads, reads + 3);
+        assert_eq!(bufreader.stats().hits, 1);
+        assert_eq!(bufreader.stats().bytes_served_from_cache, 70);
+        assert_eq!(bufreader.stats().bytes_served_from_partial_hits, 10);
+
+        // At the end there's nothing to read, and it isn't a hit
+        let mut bufs = [IoSliceMut::new(&mut a)];
+        assert_eq!(bufreader.read_vectored(&mut bufs).unwrap(), 0);
+        assert_eq!(bufreader.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_builder() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(32)
+            .max_cached_bytes(64)
+            .build(reader);
+
+        // Small reads are fetched a read size at a time
+        let mut buf = [0; 4];
+        for _ in 0..25 {
+            bufreader.read_exact(&mut buf).unwrap();
+        }
+        assert_eq!(buf, [96, 97, 98, 99]);
+        assert_eq!(bufreader.inner.reads, 4);
+        assert!(bufreader.cached_bytes() <= 64);
+
+        // The defaults match new()
+        let bufreader = SaturatingReaderBuilder::new().build(Cursor::new(vec![0u8]));
+        assert_eq!(bufreader.bufread_size, 8 * 1024);
+        assert_eq!(bufreader.max_read_size, DEFAULT_MAX_READ_SIZE);
+    }
+
+    #[test]
+    fn test_max_buffers() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(4)
+            .max_buffers(3)
+            .build(reader);
+
+        let mut buf = [0; 4];
+        for offset in (0..256).step_by(10) {
+            bufreader.read_exact_at(offset, &mut buf).unwrap();
+            assert!(bufreader.cache.buffers.len() <= 3);
+        }
+        // The most recent reads are the ones kept
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(230, 234), (240, 244), (250, 254)]
+        );
+    }
+
+    #[test]
+    fn test_borrow_range() {
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(16)
+            .merge_policy(MergePolicy::Never)
+            .build(Cursor::new((0..=255).collect::<Vec<_>>()));
+        bufreader.fetch_range(0, 16).unwrap();
+        bufreader.fetch_range(16, 16).unwrap();
+
+        assert_eq!(
+            bufreader.borrow_range(4, 12),
+            Some((4..16).collect::<Vec<_>>().as_slice())
+        );
+        assert_eq!(bufreader.borrow_range(16, 0), Some([].as_slice()));
+        // Across the boundary between buffers, and past what's buffered
+        assert_eq!(bufreader.borrow_range(10, 10), None);
+        assert_eq!(bufreader.borrow_range(20, 20), None);
+        assert_eq!(bufreader.borrow_range(100, 1), None);
+        assert_eq!(bufreader.position(), 0);
+    }
+
+    #[test]
+    fn test_into_cached_vec() {
+        let data = (0..=255).collect::<Vec<_>>();
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(data.clone()));
+        bufreader.load_all().unwrap();
+        assert_eq!(bufreader.into_cached_vec(), Some(data.clone()));
+
+        // Fragmented
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(data.clone()));
+        bufreader.fetch_range(0, 16).unwrap();
+        bufreader.fetch_range(32, 16).unwrap();
+        assert_eq!(bufreader.into_cached_vec(), None);
+
+        // Not starting at zero
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(data.clone()));
+        bufreader.fetch_range(16, 240).unwrap();
+        assert_eq!(bufreader.into_cached_vec(), None);
+
+        // Short of the known end
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(data.clone()));
+        bufreader.fetch_range(0, 100).unwrap();
+        bufreader.stream_len().unwrap();
+        assert_eq!(bufreader.into_cached_vec(), None);
+
+        assert_eq!(SaturatingReader::from(data).into_cached_vec(), None);
+    }
+
+    #[test]
+    fn test_peak_cached_bytes() {
+        let mut bufreader =
+            SaturatingReader::with_capacity(64, Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut buf = [0; 200];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(bufreader.peak_cached_bytes(), 200);
+
+        // Dropping buffered data leaves the peak where it was
+        bufreader.invalidate(0, 150);
+        bufreader.reset_stats();
+        assert_eq!(bufreader.cached_bytes(), 50);
+        assert_eq!(bufreader.peak_cached_bytes(), 200);
+
+        bufreader.read_exact_at(0, &mut buf[..64]).unwrap();
+        assert_eq!(bufreader.cached_bytes(), 114);
+        assert_eq!(bufreader.peak_cached_bytes(), 200);
+        assert_eq!(bufreader.stats().peak_cached_bytes, 114);
+
+        // The peak counts what was held before eviction brought it back within the limit
+        let mut bufreader = SaturatingReader::with_capacity_and_limit(
+            64,
+            100,
+            Cursor::new((0..=255).collect::<Vec<_>>()),
+        );
+        for _ in 0..4 {
+            bufreader.read_exact(&mut buf[..50]).unwrap();
+        }
+        assert!(bufreader.cached_bytes() <= 100);
+        assert!(bufreader.peak_cached_bytes() > 100);
+        assert_eq!(
+            bufreader.stats().peak_cached_bytes,
+            bufreader.peak_cached_bytes()
+        );
+    }
+
+    #[test]
+    fn test_on_drop() {
+        let reported = Arc::new(Mutex::new(vec![]));
+        let mut bufreader = {
+            let reported = reported.clone();
+            SaturatingReaderBuilder::new()
+                .read_size(64)
+                .max_cached_bytes(128)
+                .on_drop(move |stats| reported.lock().unwrap().push(stats))
+                .build(Cursor::new((0..=255).collect::<Vec<_>>()))
+        };
+
+        // Clones don't get the callback
+        drop(bufreader.clone());
+        assert!(reported.lock().unwrap().is_empty());
+
+        let mut buf = [0; 10];
+        bufreader.read_exact_at(0, &mut buf).unwrap();
+        bufreader.read_exact_at(5, &mut buf).unwrap();
+        bufreader.read_exact_at(100, &mut buf).unwrap();
+        bufreader.read_exact_at(200, &mut buf).unwrap();
+        assert!(bufreader.cached_bytes() <= 128);
+        let inner = bufreader.into_inner();
+        assert_eq!(inner.get_ref().len(), 256);
+
+        // The reader reported once it was consumed
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].hits, 1);
+        assert_eq!(reported[0].misses, 3);
+        assert_eq!(reported[0].hit_ratio(), 0.25);
+        assert_eq!(reported[0].bytes_read_from_inner, 64 + 64 + 56);
+        assert_eq!(reported[0].peak_cached_bytes, 128 + 56);
+
+        // Nothing happens without a callback
+        drop(SaturatingReader::new(Cursor::new(vec![0; 10])));
+    }
+
+    #[test]
+    fn test_read_range_exact() {
+        let reader = CountingReader::new(Cursor::new((0..100).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.fetch_range(10, 10).unwrap();
+
+        assert_eq!(
+            bufreader.read_range_exact(10, 20).unwrap(),
+            (10..30).collect::<Vec<_>>()
+        );
+        assert_eq!(bufreader.read_range_exact(99, 1).unwrap(), vec![99]);
+        assert!(bufreader.read_range_exact(50, 0).unwrap().is_empty());
+        assert_eq!(bufreader.position(), 0);
+
+        // Running off the end
+        let err = bufreader.read_range_exact(90, 20).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        // Once the length is known, nothing is read at all
+        bufreader.stream_len().unwrap();
+        let reads = bufreader.inner.reads;
+        let err = bufreader.read_range_exact(50, u64::MAX / 2).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(bufreader.inner.reads, reads);
+    }
+
+    #[test]
+    fn test_fill_region_batched() {
+        for (max_gap, reads) in [(8, 1), (7, 2)] {
+            let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+            let mut bufreader = SaturatingReader::with_capacity(8, reader);
+            bufreader.fetch_range(8, 8).unwrap();
+            bufreader.fetch_range(24, 8).unwrap();
+            let before = bufreader.inner.reads;
+
+            // Holes at [0, 8) and [16, 24), 8 bytes apart
+            bufreader.fill_region_batched(0, 24, max_gap).unwrap();
+            assert_eq!(bufreader.inner.reads - before, reads);
+            assert_eq!(bufreader.cached_ranges().collect::<Vec<_>>(), vec![(0, 32)]);
+
+            let mut buf = [0; 32];
+            bufreader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), (0..32).collect::<Vec<_>>().as_slice());
+        }
+    }
+
+    #[test]
+    fn test_read_uncached() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.prefetch(0, 16).unwrap();
+
+        bufreader.seek(SeekFrom::Start(8)).unwrap();
+        let mut buf = [0; 200];
+        assert_eq!(bufreader.read_uncached(&mut buf).unwrap(), 200);
+        assert_eq!(buf.as_slice(), (8..208).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.position(), 208);
+        assert_eq!(bufreader.cached_ranges().collect::<Vec<_>>(), vec![(0, 16)]);
+
+        // Carries on from the cursor, as a normal read would
+        let mut buf = [0; 100];
+        assert_eq!(bufreader.read_uncached(&mut buf).unwrap(), 48);
+        assert_eq!(&buf[..48], (208..=255).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.read_uncached(&mut buf).unwrap(), 0);
+        assert_eq!(bufreader.cached_ranges().collect::<Vec<_>>(), vec![(0, 16)]);
+    }
+
+    #[test]
+    fn test_copy_to() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(64, reader);
+        bufreader.prefetch(64, 128).unwrap();
+        let reads = bufreader.inner.reads;
+
+        bufreader.seek(SeekFrom::Start(32)).unwrap();
+        let mut out = vec![];
+        assert_eq!(bufreader.copy_to(&mut out).unwrap(), 224);
+        assert_eq!(out, (32..=255).collect::<Vec<_>>());
+        assert_eq!(bufreader.position(), 256);
+
+        // Only [32, 64) and [192, 256) had to be fetched, plus the read which found the end
+        assert_eq!(bufreader.inner.reads, reads + 3);
+        assert_eq!(bufreader.stats().bytes_read_from_inner, 256 - 32);
+        assert_eq!(bufreader.copy_to(&mut out).unwrap(), 0);
+
+        // Nothing is copied past a logical end
+        bufreader.set_logical_eof(100);
+        bufreader.seek(SeekFrom::Start(90)).unwrap();
+        let mut out = vec![];
+        assert_eq!(bufreader.copy_to(&mut out).unwrap(), 10);
+        assert_eq!(out, (90..100).collect::<Vec<_>>());
+
+        // At the end, the inner reader isn't asked for anything, even with the quota used up
+        let mut bufreader = SaturatingReader::with_read_quota(100, Cursor::new(vec![0; 256]));
+        bufreader.fetch_range(0, 100).unwrap();
+        bufreader.set_logical_eof(100);
+        bufreader.set_position(100);
+        let misses = bufreader.stats().misses;
+        assert_eq!(bufreader.copy_to(&mut out).unwrap(), 0);
+        assert_eq!(bufreader.stats().misses, misses);
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let data = (0..=255).collect::<Vec<_>>();
+
+        let mut bufreader: SaturatingReader<_> = data.as_slice().into();
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, data);
+        assert_eq!(bufreader.read_size(), 8 * 1024);
+
+        let mut bufreader: SaturatingReader<_> = data.clone().into();
+        bufreader.seek(SeekFrom::End(-6)).unwrap();
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, (250..=255).collect::<Vec<_>>());
+        assert_eq!(bufreader.into_inner().into_inner(), data);
+    }
+
+    #[test]
+    fn test_inner_bytes_read() {
+        let mut bufreader =
+            SaturatingReader::with_capacity(64, Cursor::new((0..=255).collect::<Vec<_>>()));
+        assert_eq!(bufreader.inner_bytes_read(), 0);
+
+        // A cold read pulls in a whole read size
+        let mut buf = [0; 10];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(bufreader.inner_bytes_read(), 64);
+
+        // Warm reads don't add anything, and resetting the stats leaves it alone
+        bufreader.read_exact(&mut buf).unwrap();
+        bufreader.reset_stats();
+        assert_eq!(bufreader.inner_bytes_read(), 64);
+
+        bufreader.read_at(200, &mut buf).unwrap();
+        assert_eq!(bufreader.inner_bytes_read(), 64 + 56);
+    }
+
+    #[test]
+    fn test_cancel_flag() {
+        let reader = CountingReader::new(Cursor::new((0..200).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(100, reader);
+        let cancel = Arc::new(AtomicBool::new(false));
+        bufreader.set_cancel_flag(cancel.clone());
+
+        let mut buf = [0; 50];
+        assert_eq!(bufreader.read(&mut buf).unwrap(), 50);
+
+        // Cold reads are abandoned without touching the inner reader
+        cancel.store(true, Ordering::Relaxed);
+        let err = bufreader.read_at(150, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<SaturatingError>(),
+            Some(&SaturatingError::Cancelled)
+        );
+        assert_eq!(bufreader.inner.reads, 1);
+        assert!(bufreader.read_exact_at(150, &mut buf).is_err());
+
+        // Warm reads still go ahead
+        assert_eq!(bufreader.read(&mut buf).unwrap(), 50);
+        assert_eq!(buf.as_slice(), (50..100).collect::<Vec<_>>().as_slice());
+
+        cancel.store(false, Ordering::Relaxed);
+        assert_eq!(bufreader.read_at(150, &mut buf).unwrap(), 50);
+    }
+
+    #[test]
+    fn test_diff_caches() {
+        let data = (0..100).collect::<Vec<_>>();
+        let mut a = SaturatingReader::new(Cursor::new(data.clone()));
+        let mut buf = vec![];
+        a.read_to_end(&mut buf).unwrap();
+
+        // Only part of the source is buffered, and some of that is stale
+        let mut stale = data[20..60].to_vec();
+        stale[10..15].fill(0);
+        stale[30] = 0;
+        let b = SaturatingReader::with_buffers(
+            Cursor::new(data.clone()),
+            vec![
+                Buffer::from_slice(20, &stale),
+                Buffer::from_slice(80, &[0; 5]),
+            ],
+        );
+
+        assert_eq!(diff_caches(&a, &b), vec![(30, 35), (50, 51), (80, 85)]);
+        assert_eq!(diff_caches(&b, &a), diff_caches(&a, &b));
+        assert!(diff_caches(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn test_set_read_size() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(64, reader);
+        assert_eq!(bufreader.read_size(), 64);
+
+        let mut buf = [0; 1];
+        while bufreader.position() < 128 {
+            bufreader.read_exact(&mut buf).unwrap();
+        }
+        assert_eq!(bufreader.inner.reads, 2);
+
+        // Smaller fetches from here on
+        bufreader.set_read_size(16);
+        assert_eq!(bufreader.read_size(), 16);
+        while bufreader.position() < 256 {
+            bufreader.read_exact(&mut buf).unwrap();
+        }
+        assert_eq!(bufreader.inner.reads, 2 + 8);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 256)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "read size must be non-zero")]
+    fn test_zero_read_size() {
+        SaturatingReader::new(Cursor::new(vec![0; 10])).set_read_size(0);
+    }
+
+    #[test]
+    fn test_coalesce_partial_reads() {
+        /// Hands over a single byte per read.
+        struct Trickle<R>(R);
+
+        impl<R: Read> Read for Trickle<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let len = buf.len().min(1);
+                self.0.read(&mut buf[..len])
+            }
+        }
+
+        impl<R: Seek> Seek for Trickle<R> {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        for (coalesce, misses) in [(false, 32), (true, 1)] {
+            let reader = CountingReader::new(Trickle(Cursor::new((0..=255).collect::<Vec<_>>())));
+            let mut bufreader = SaturatingReaderBuilder::new()
+                .read_size(16)
+                .coalesce_partial_reads(coalesce)
+                .build(reader);
+
+            let mut buf = [0; 32];
+            assert_eq!(bufreader.read(&mut buf).unwrap(), 32);
+            assert_eq!(buf.as_slice(), (0..32).collect::<Vec<_>>().as_slice());
+            assert_eq!(bufreader.stats().misses, misses);
+            assert_eq!(bufreader.cached_ranges().collect::<Vec<_>>(), vec![(0, 32)]);
+            assert_eq!(bufreader.inner.reads, 32);
+        }
+    }
+
+    #[test]
+    fn test_available() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        assert_eq!(bufreader.available(), 0);
+
+        bufreader.fetch_range(0, 16).unwrap();
+        assert_eq!(bufreader.available(), 16);
+        bufreader.set_position(10);
+        assert_eq!(bufreader.available(), 6);
+
+        // Grows once the next range is merged in
+        bufreader.fetch_range(16, 16).unwrap();
+        assert_eq!(bufreader.available(), 22);
+
+        // And nothing is available somewhere uncached, with nothing fetched to find out
+        let reads = bufreader.inner.reads;
+        bufreader.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(bufreader.available(), 0);
+        assert_eq!(bufreader.inner.reads, reads);
+    }
+
+    #[test]
+    fn test_drop_range() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.fetch_range(0, 100).unwrap();
+
+        bufreader.drop_range(40, 60);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 40), (60, 100)]
+        );
+        assert_eq!(bufreader.cached_bytes(), 80);
+
+        // Reading it again fetches just what was dropped
+        let reads = bufreader.inner.reads;
+        let mut buf = [0; 100];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..100).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, reads + 1);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 100)]
+        );
+    }
+
+    #[test]
+    fn test_read_empty() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        assert_eq!(bufreader.read(&mut []).unwrap(), 0);
+        assert_eq!(bufreader.read_at(100, &mut []).unwrap(), 0);
+        assert_eq!(bufreader.inner.reads, 0);
+        assert_eq!(bufreader.inner.seeks, 0);
+        assert_eq!(bufreader.stats(), CacheStats::default());
+    }
+
+    #[test]
+    #[cfg(feature = "nightly")]
+    fn test_read_buf() {
+        use std::{io::BorrowedBuf, mem::MaybeUninit};
+
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        let mut storage = [MaybeUninit::uninit(); 32];
+
+        // Nothing is buffered yet, so this goes the usual way
+        let mut buf = BorrowedBuf::from(storage.as_mut_slice());
+        bufreader.read_buf(buf.unfilled()).unwrap();
+        assert_eq!(buf.filled(), (0..32).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, 1);
+
+        // What's buffered is copied straight in, without initialising the rest
+        bufreader.rewind().unwrap();
+        let mut storage = [MaybeUninit::uninit(); 64];
+        let mut buf = BorrowedBuf::from(storage.as_mut_slice());
+        bufreader.read_buf(buf.unfilled()).unwrap();
+        assert_eq!(buf.filled(), (0..32).collect::<Vec<_>>().as_slice());
+        assert!(!buf.unfilled().is_init());
+        assert_eq!(bufreader.inner.reads, 1);
+        assert_eq!(bufreader.stats().hits, 1);
+        assert_eq!(bufreader.position(), 32);
+
+        // Nothing buffered past the logical end is copied in
+        bufreader.set_logical_eof(40);
+        let mut buf = BorrowedBuf::from(storage.as_mut_slice());
+        bufreader.read_buf(buf.unfilled()).unwrap();
+        assert_eq!(buf.filled(), (32..40).collect::<Vec<_>>().as_slice());
+        let mut buf = BorrowedBuf::from(storage.as_mut_slice());
+        bufreader.read_buf(buf.unfilled()).unwrap();
+        assert!(buf.filled().is_empty());
+        assert_eq!(bufreader.position(), 40);
+    }
+
+    #[test]
+    fn test_read_exact_at() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.fetch_range(20, 30).unwrap();
+        let reads = bufreader.inner.reads;
+
+        // Only the parts either side of what's buffered are fetched
+        let mut buf = [0; 50];
+        bufreader.read_exact_at(10, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (10..60).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, reads + 2);
+        assert_eq!(bufreader.position(), 0);
+    }
+
+    #[test]
+    fn test_read_exact_at_eof() {
+        let mut bufreader = SaturatingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut buf = [0; 20];
+        assert_eq!(
+            bufreader.read_exact_at(250, &mut buf).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        assert_eq!(bufreader.position(), 0);
+
+        bufreader.read_exact_at(236, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (236..=255).collect::<Vec<_>>().as_slice());
+    }
+
+    #[test]
+    fn test_read_available() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.fetch_range(100, 20).unwrap();
+        bufreader.fetch_range(130, 10).unwrap();
+        let reads = bufreader.inner.reads;
+
+        // Half is buffered, and the rest is handed back to be fetched
+        bufreader.set_position(100);
+        let mut buf = [0; 40];
+        assert_eq!(bufreader.read_available(&mut buf), (20, Some((120, 140))));
+        assert_eq!(&buf[..20], (100..120).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.position(), 120);
+
+        // Nothing is buffered here at all
+        assert_eq!(bufreader.read_available(&mut buf), (0, Some((120, 160))));
+        assert_eq!(bufreader.position(), 120);
+
+        bufreader.set_position(130);
+        assert_eq!(bufreader.read_available(&mut buf[..10]), (10, None));
+        assert_eq!(bufreader.position(), 140);
+        assert_eq!(bufreader.inner.reads, reads);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let data = (0..=255).collect::<Vec<_>>();
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(data.clone()));
+        let chunks = bufreader
+            .chunks(100)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            chunks.iter().map(|chunk| chunk.len()).collect::<Vec<_>>(),
+            vec![100, 100, 56]
+        );
+        assert_eq!(chunks.concat(), data);
+
+        // Everything comes from the cache the second time round
+        let bytes_read = bufreader.stats().bytes_read_from_inner;
+        bufreader.rewind().unwrap();
+        let chunks = bufreader.chunks(64).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks.concat(), data);
+        assert_eq!(bufreader.stats().bytes_read_from_inner, bytes_read);
+    }
+
+    #[test]
+    fn test_try_read_cached() {
+        let inner = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(16, inner);
+        let mut buf = [0; 10];
+        assert_eq!(bufreader.try_read_cached(&mut buf).unwrap(), None);
+        assert_eq!(bufreader.position(), 0);
+        assert_eq!(bufreader.stats().misses, 0);
+
+        bufreader.prefetch(0, 16).unwrap();
+        assert_eq!(bufreader.try_read_cached(&mut buf).unwrap(), Some(10));
+        assert_eq!(buf.as_slice(), (0..10).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.position(), 10);
+
+        // Only part of this is buffered, so none of it is read
+        assert_eq!(bufreader.try_read_cached(&mut buf).unwrap(), None);
+        assert_eq!(bufreader.position(), 10);
+        assert_eq!(bufreader.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(vec![0; 256]));
+        assert_eq!(
+            bufreader.to_string(),
+            "SaturatingReader at 0: 0 buffers holding 0 bytes"
+        );
+
+        bufreader.fetch_range(100, 50).unwrap();
+        let mut buf = [0; 20];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(
+            bufreader.to_string(),
+            "SaturatingReader at 20: 2 buffers holding 70 bytes in [0, 20), [100, 150)"
+        );
+    }
+
+    #[test]
+    fn test_cached_chunks() {
+        let data = (0..=255).collect::<Vec<_>>();
+        let mut bufreader = SaturatingReader::with_capacity(16, Cursor::new(data.clone()));
+        bufreader.fetch_range(10, 20).unwrap();
+        bufreader.fetch_range(100, 50).unwrap();
+        bufreader.fetch_range(250, 10).unwrap();
+
+        // Rebuild a sparse copy of the source from what's buffered
+        let mut sparse = vec![0; 256];
+        for (start, chunk) in bufreader.cached_chunks() {
+            sparse[start as usize..start as usize + chunk.len()].copy_from_slice(chunk);
+        }
+        for (start, end) in bufreader.cached_ranges() {
+            assert_eq!(
+                &sparse[start as usize..end as usize],
+                &data[start as usize..end as usize]
+            );
+        }
+        assert_eq!(
+            bufreader
+                .cached_chunks()
+                .map(|(start, chunk)| (start, chunk.len()))
+                .collect::<Vec<_>>(),
+            vec![(10, 20), (100, 50), (250, 6)]
+        );
+    }
+
+    #[test]
+    fn test_adaptive_readahead() {
+        let data = (0..1024 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+        let scan = |bufreader: &mut SaturatingReader<CountingReader<Cursor<Vec<u8>>>>| {
+            let mut buf = [0; 1024];
+            while bufreader.read(&mut buf).unwrap() > 0 {}
+            bufreader.inner.reads
+        };
+
+        let reader = CountingReader::new(Cursor::new(data.clone()));
+        let fixed_reads = scan(&mut SaturatingReader::with_capacity(4096, reader));
+
+        let reader = CountingReader::new(Cursor::new(data));
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(4096)
+            .max_readahead(64 * 1024)
+            .build(reader);
+        let adaptive_reads = scan(&mut bufreader);
+        assert_eq!(fixed_reads, 257);
+        assert!(adaptive_reads < fixed_reads / 8);
+        assert_eq!(bufreader.readahead, 64 * 1024);
+
+        // A miss somewhere else starts again from the read size
+        bufreader.clear();
+        bufreader.seek(SeekFrom::Start(1000)).unwrap();
+        bufreader.read_exact(&mut [0; 10]).unwrap();
+        assert_eq!(bufreader.readahead, 4096);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(1000, 5096)]
+        );
+    }
+
+    #[test]
+    fn test_cached_reader() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.fetch_range(0, 100).unwrap();
+
+        let mut data = vec![];
+        bufreader
+            .cached_reader(10, 50)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, (10..60).collect::<Vec<_>>());
+
+        assert!(bufreader.cached_reader(90, 20).is_none());
+        assert!(bufreader.cached_reader(100, 1).is_none());
+    }
+
+    #[test]
+    fn test_lines() {
+        let text = "first line\nsecond line\n\nfourth line after an empty one\nlast";
+        let reader = Cursor::new(text.as_bytes().to_vec());
+        let mut bufreader = SaturatingReader::with_capacity(8, reader);
+
+        let lines = bufreader.by_ref().lines().collect::<Result<Vec<_>, _>>();
+        assert_eq!(lines.unwrap(), text.lines().collect::<Vec<_>>());
+
+        // Everything was merged into one buffer, so going back hands over the whole thing
+        bufreader.seek(SeekFrom::Start(6)).unwrap();
+        assert_eq!(bufreader.fill_buf().unwrap(), &text.as_bytes()[6..]);
+        assert_eq!(bufreader.cache.buffers.len(), 1);
+    }
+
+    #[test]
+    fn test_read_at() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+
+        let mut buf = [0; 16];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..16).collect::<Vec<_>>().as_slice());
+
+        // Positioned reads don't disturb the sequential scan
+        let n = bufreader.read_at(100, &mut buf).unwrap();
+        assert_eq!(n, 16);
+        assert_eq!(buf.as_slice(), (100..116).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.stream_position().unwrap(), 16);
+
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (16..32).collect::<Vec<_>>().as_slice());
+
+        // The positioned read was kept and merged like any other
+        let n = bufreader.read_at(8, &mut buf).unwrap();
+        assert_eq!(n, 16);
+        assert_eq!(buf.as_slice(), (8..24).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.stream_position().unwrap(), 32);
+        assert_eq!(bufreader.cache.buffers.len(), 2);
+        assert_eq!(bufreader.cache.buffers[0].range(), (0, 32));
+        assert_eq!(bufreader.cache.buffers[1].range(), (100, 116));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let reader = Cursor::new((0..=255).cycle().take(64 * 1024).collect::<Vec<u8>>());
+        let mut bufreader = SaturatingReader::with_capacity_and_limit(64, 1024, reader);
+
+        // Scattered reads, each landing in their own buffer
+        let mut buf = [0; 64];
+        for offset in (0..64 * 1024).step_by(256) {
+            bufreader.read_at(offset, &mut buf).unwrap();
+            assert!(bufreader.cached_bytes() <= 1024);
+        }
+        assert_eq!(bufreader.cache.buffers.len(), 16);
+
+        // Keep the first one warm while reading elsewhere
+        bufreader.read_at(64 * 1024 - 256, &mut buf).unwrap();
+        for offset in (0..32 * 1024).step_by(256) {
+            bufreader.read_at(offset, &mut buf).unwrap();
+            bufreader.read_at(64 * 1024 - 256, &mut buf).unwrap();
+        }
+        assert!(bufreader.cached_bytes() <= 1024);
+        assert!(bufreader
+            .cache
+            .buffers
+            .iter()
+            .any(|b| b.range() == (64 * 1024 - 256, 64 * 1024 - 192)));
+        assert!(bufreader
+            .cache
+            .buffers
+            .iter()
+            .any(|b| b.range() == (32 * 1024 - 256, 32 * 1024 - 192)));
+
+        // A sequential scan merges into one buffer, which gets trimmed from the front
+        bufreader.seek(SeekFrom::Start(0)).unwrap();
+        let mut buf = [0; 100];
+        for i in 0..100 {
+            bufreader.read_exact(&mut buf).unwrap();
+            assert_eq!(
+                buf.as_slice(),
+                (i * 100..(i + 1) * 100)
+                    .map(|x| x as u8)
+                    .collect::<Vec<_>>()
+                    .as_slice()
+            );
+            assert!(bufreader.cached_bytes() <= 1024);
+        }
+        let last = bufreader.cache.buffers.iter().max_by_key(|b| b.range().1);
+        assert_eq!(last.unwrap().range().1, 10_000);
+    }
+
+    #[test]
+    fn test_pin() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity_and_limit(16, 64, reader);
+        bufreader.pin(0, 16).unwrap();
+        assert_eq!(bufreader.inner.reads, 1);
+
+        // Read a lot more than the limit elsewhere
+        let mut buf = [0; 8];
+        bufreader.seek(SeekFrom::Start(100)).unwrap();
+        for _ in 0..15 {
+            bufreader.read_exact(&mut buf).unwrap();
+        }
+        assert!(bufreader.cached_bytes() <= 64);
+
+        // The header is still there, and even reading next to it leaves it alone
+        bufreader.seek(SeekFrom::Start(16)).unwrap();
+        bufreader.read_exact(&mut [0; 40]).unwrap();
+        let reads = bufreader.inner.reads;
+        let mut buf = [0; 16];
+        bufreader.read_at(0, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..16).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, reads);
+
+        // Once unpinned it goes like anything else
+        bufreader.unpin(0, 16);
+        bufreader.seek(SeekFrom::Start(200)).unwrap();
+        bufreader.read_exact(&mut [0; 56]).unwrap();
+        assert_eq!(bufreader.missing_ranges(0, 16), vec![(0, 16)]);
+    }
+
+    #[test]
+    fn test_pinned_over_limit() {
+        let data = (0..4096).map(|i| i as u8).collect::<Vec<_>>();
+        let mut bufreader = SaturatingReader::with_capacity_and_limit(200, 64, Cursor::new(data));
+        bufreader.pin(1000, 2000).unwrap();
+
+        // The pinned bytes alone are over the limit, so everything else has to go
+        for _ in 0..308 / 4 {
+            bufreader.read_exact(&mut [0; 4]).unwrap();
+        }
+        bufreader.seek(SeekFrom::Start(100)).unwrap();
+        let mut buf = [0; 8];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (100..108).collect::<Vec<_>>().as_slice());
+        assert!(bufreader.missing_ranges(1000, 1000).is_empty());
+        assert!(bufreader.cached_bytes() <= 1000 + 200);
+    }
+
+    #[test]
+    #[cfg(feature = "checksum")]
+    fn test_corrupt_buffer_refetched() {
+        let mut bufreader = SaturatingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut buf = [0; 100];
+        bufreader.read_exact(&mut buf).unwrap();
+        let misses = bufreader.stats().misses;
+
+        bufreader.cache.buffers[0].corrupt(10, &[0; 10]);
+
+        // The corrupted buffer is thrown away and fetched again, rather than handed out
+        bufreader.rewind().unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..100).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.stats().misses, misses + 1);
+
+        // Once it's been replaced, reads are served from the cache again
+        bufreader.rewind().unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(bufreader.stats().misses, misses + 1);
+    }
+
+    #[test]
+    #[cfg(feature = "checksum")]
+    fn test_corrupt_buffer_not_handed_over() {
+        let new_reader = || {
+            let mut bufreader = SaturatingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+            bufreader.load_all().unwrap();
+            bufreader.cache.buffers[0].corrupt(10, &[0; 10]);
+            bufreader
+        };
+
+        assert!(new_reader().into_cached_vec().is_none());
+        let (_, buffers, _) = new_reader().into_parts();
+        assert!(buffers.is_empty());
+
+        let mut saved = vec![];
+        new_reader().save_cache(&mut saved).unwrap();
+        let mut bufreader = SaturatingReader::new(Cursor::new(vec![]));
+        bufreader.load_cache(&mut saved.as_slice()).unwrap();
+        assert_eq!(bufreader.cached_bytes(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "checksum")]
+    fn test_corrupt_buffer_not_borrowed() {
+        let mut bufreader = SaturatingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        bufreader.fetch_range(0, 100).unwrap();
+        bufreader.cache.buffers[0].corrupt(10, &[0; 10]);
+
+        // Every path that hands out cached data treats the corrupted buffer as missing
+        assert!(bufreader.borrow_range(0, 20).is_none());
+        assert!(bufreader.cached_reader(0, 20).is_none());
+        assert_eq!(bufreader.cached_chunks().count(), 0);
+        assert!(!bufreader.is_cached(0, 100));
+        assert!(bufreader.cache().get(0).is_none());
+        assert_eq!(bufreader.cache().read_at(0, &mut [0; 20]), 0);
+
+        // Fetching again replaces it with clean data
+        bufreader.fetch_range(0, 100).unwrap();
+        assert_eq!(
+            bufreader.borrow_range(0, 100).unwrap(),
+            (0..100).collect::<Vec<_>>().as_slice()
+        );
+    }
+
+    #[test]
+    fn test_mark_hole() {
+        let data = (0..=255)
+            .map(|i| if (50..200).contains(&i) { 0 } else { i as u8 })
+            .collect::<Vec<_>>();
+        let reader = CountingReader::new(Cursor::new(data.clone()));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.mark_hole(50, 200);
+
+        // Only the data either side of the hole is fetched
+        let mut buf = [1; 256];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), data.as_slice());
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 50), (200, 256)]
+        );
+        assert!(bufreader.missing_ranges(0, 256).is_empty());
+
+        // Reading within the hole never touches the inner reader
+        let reads = bufreader.inner.reads;
+        let mut buf = [1; 100];
+        bufreader.read_at(75, &mut buf).unwrap();
+        assert_eq!(buf, [0; 100]);
+        bufreader.fetch_range(0, 256).unwrap();
+        assert_eq!(bufreader.inner.reads, reads);
+    }
+
+    #[test]
+    fn test_mark_hole_buf_read() {
+        let reader = CountingReader::new(Cursor::new(vec![7; 256]));
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        bufreader.mark_hole(64, 192);
+
+        // The hole is handed out as zeros without fetching it
+        bufreader.seek(SeekFrom::Start(64)).unwrap();
+        assert_eq!(bufreader.available(), 128);
+        assert_eq!(bufreader.fill_buf().unwrap(), [0; 128]);
+        assert_eq!(bufreader.inner.reads, 0);
+
+        // Copying fetches only the data either side of it
+        bufreader.rewind().unwrap();
+        let mut out = vec![];
+        assert_eq!(bufreader.copy_to(&mut out).unwrap(), 256);
+        assert_eq!(&out[..64], [7; 64]);
+        assert_eq!(&out[64..192], [0; 128]);
+        assert_eq!(&out[192..], [7; 64]);
+        assert!(bufreader.missing_ranges(64, 128).is_empty());
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 64), (192, 256)]
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::new(reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).unwrap();
+        bufreader.clear();
+        assert!(bufreader.cache.buffers.is_empty());
+        assert_eq!(bufreader.stream_position().unwrap(), 64);
+
+        // Reads still work, they just have to go back to the inner reader
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (64..128).collect::<Vec<_>>().as_slice());
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).unwrap();
+        bufreader.read_at(128, &mut buf).unwrap();
+
+        // The middle of a buffer leaves two behind
+        bufreader.invalidate(16, 32);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 16), (32, 64), (128, 192)]
+        );
+        assert_eq!(bufreader.stream_position().unwrap(), 64);
+
+        // An exact match drops it entirely
+        bufreader.invalidate(128, 192);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 16), (32, 64)]
+        );
+
+        // Partial overlaps trim the ends, and reading back fills the gaps in again
+        bufreader.invalidate(8, 40);
+        bufreader.read_at(0, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..64).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.cache.buffers.len(), 1);
+        assert_eq!(bufreader.cache.buffers[0].range(), (0, 64));
+    }
+
+    #[test]
+    fn test_cached_ranges() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+        assert_eq!(bufreader.cached_ranges().count(), 0);
+
+        // Read out of order
+        let mut buf = [0; 16];
+        bufreader.read_at(200, &mut buf).unwrap();
+        bufreader.read_at(0, &mut buf).unwrap();
+        bufreader.read_at(100, &mut buf).unwrap();
+        bufreader.read_at(8, &mut buf).unwrap();
+
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 24), (100, 116), (200, 216)]
+        );
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+
+        bufreader.prefetch(0, 100).unwrap();
+        assert_eq!(bufreader.inner.reads, 1);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 100)]
+        );
+
+        // Everything is served from the cache
+        let mut buf = [0; 100];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..100).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, 1);
+
+        // Only the uncached tail is fetched
+        bufreader.prefetch(50, 100).unwrap();
+        assert_eq!(bufreader.inner.reads, 2);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 150)]
+        );
+
+        // Running off the end just stops at EOF
+        bufreader.prefetch(200, 100).unwrap();
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 150), (200, 256)]
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(128, reader);
+
+        // Cold read
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(
+            bufreader.stats(),
+            CacheStats {
+                hits: 0,
+                misses: 1,
+                bytes_served_from_cache: 0,
+                bytes_served_from_partial_hits: 0,
+                bytes_read_from_inner: 128,
+                peak_cached_bytes: 128,
+            }
+        );
+
+        // Repeated read
+        bufreader.rewind().unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(
+            bufreader.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                bytes_served_from_cache: 64,
+                bytes_served_from_partial_hits: 0,
+                bytes_read_from_inner: 128,
+                peak_cached_bytes: 128,
+            }
+        );
+
+        // Half of this is already buffered
+        bufreader.seek(SeekFrom::Start(96)).unwrap();
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(
+            bufreader.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                bytes_served_from_cache: 96,
+                bytes_served_from_partial_hits: 32,
+                bytes_read_from_inner: 256,
+                peak_cached_bytes: 256,
+            }
+        );
+
+        assert_eq!(bufreader.stats().bytes_served_from_full_hits(), 64);
+
+        bufreader.reset_stats();
+        assert_eq!(bufreader.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_bytes_deduplicated() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::with_capacity(16, reader);
+        let mut buf = [0; 96];
+        bufreader.read_exact_at(0, &mut buf).unwrap();
+        assert_eq!(bufreader.stats().bytes_deduplicated(), 0);
+
+        // The first half of this is reused, and only the second half fetched
+        let mut buf = [0; 64];
+        bufreader.read_exact_at(64, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (64..128).collect::<Vec<_>>().as_slice());
+        let stats = bufreader.stats();
+        assert_eq!(stats.bytes_deduplicated(), 32);
+        assert_eq!(stats.bytes_served_from_partial_hits, 32);
+        assert_eq!(stats.bytes_served_from_full_hits(), 0);
+    }
+
+    #[test]
+    fn test_export_coverage() {
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(16)
+            .merge_policy(MergePolicy::Never)
+            .build(Cursor::new(vec![0; 1024]));
+        bufreader.fetch_range(0, 16).unwrap();
+        bufreader.fetch_range(16, 16).unwrap();
+        bufreader.fetch_range(200, 300).unwrap();
+
+        // Touching buffers come out as one range
+        let mut exported = vec![];
+        bufreader.export_coverage(&mut exported).unwrap();
+        assert_eq!(exported, vec![2, 0, 32, 168, 1, 172, 2]);
+        assert_eq!(
+            import_coverage(&mut exported.as_slice()).unwrap(),
+            vec![(0, 32), (200, 500)]
+        );
+
+        // Round trip from another reader
+        let mut other = SaturatingReader::new(Cursor::new(vec![0; 1024]));
+        other.fetch_range(1000, 24).unwrap();
+        other.fetch_range(50, 1).unwrap();
+        let mut exported = vec![];
+        other.export_coverage(&mut exported).unwrap();
+        assert_eq!(
+            import_coverage(&mut exported.as_slice()).unwrap(),
+            other.cached_ranges().collect::<Vec<_>>()
+        );
+
+        assert!(import_coverage(&mut [1, 0].as_slice()).is_err());
+        assert!(import_coverage(&mut [1, 0, 0].as_slice()).is_err());
+        assert!(import_coverage(&mut [0xff; 11].as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_clone() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(bufreader.inner.reads, 1);
+
+        // The clone already has everything the original read
+        let mut cloned = bufreader.clone();
+        cloned.rewind().unwrap();
+        cloned.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..64).collect::<Vec<_>>().as_slice());
+        assert_eq!(cloned.inner.reads, 1);
+
+        // But from here on they go their separate ways
+        cloned.read_exact(&mut buf).unwrap();
+        assert_eq!(cloned.cached_ranges().collect::<Vec<_>>(), vec![(0, 128)]);
+        assert_eq!(bufreader.cached_ranges().collect::<Vec<_>>(), vec![(0, 64)]);
+        assert_eq!(bufreader.inner.reads, 1);
+    }
+
+    #[test]
+    fn test_save_load_cache() {
+        let reader = CountingReader::new(Cursor::new((0..=255).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::with_capacity(1, reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_at(0, &mut buf).unwrap();
+        bufreader.read_at(128, &mut buf).unwrap();
+
+        let mut saved = vec![];
+        bufreader.save_cache(&mut saved).unwrap();
+
+        bufreader.clear();
+        bufreader.load_cache(&mut saved.as_slice()).unwrap();
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 64), (128, 192)]
+        );
+
+        // Reads are served straight from the restored buffers
+        bufreader.read_at(0, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..64).collect::<Vec<_>>().as_slice());
+        bufreader.read_at(128, &mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (128..192).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.inner.reads, 2);
+    }
+
+    #[test]
+    fn test_load_cache_rejects_bad_data() {
+        let reader = Cursor::new((0..=255).collect::<Vec<_>>());
+        let mut bufreader = SaturatingReader::new(reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).unwrap();
+        let mut saved = vec![];
+        bufreader.save_cache(&mut saved).unwrap();
+
+        // Wrong magic
+        let mut bad = saved.clone();
+        bad[0] = b'X';
+        let err = bufreader.load_cache(&mut bad.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // Wrong version
+        let mut bad = saved.clone();
+        bad[8] = 2;
+        let err = bufreader.load_cache(&mut bad.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // Truncated data
+        let bad = &saved[..saved.len() - 1];
+        let err = bufreader.load_cache(&mut &bad[..]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_stream_len_hint() {
+        let reader = CountingReader::new(Cursor::new((0..200).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::new(reader);
+        bufreader.set_stream_len_hint(150);
+
+        assert_eq!(bufreader.stream_len().unwrap(), 150);
+        assert_eq!(bufreader.seek(SeekFrom::End(-10)).unwrap(), 140);
+        assert_eq!(bufreader.inner.seeks, 0);
+
+        // Reads stop at the hint, even though the inner reader has more
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, (140..150).collect::<Vec<_>>());
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(140, 150)]
+        );
+        assert_eq!(bufreader.inner.reads, 1);
+    }
+
+    #[test]
+    fn test_logical_eof() {
+        let reader = CountingReader::new(Cursor::new((0..200).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::new(reader);
+        bufreader.set_logical_eof(100);
+
+        let mut buf = vec![];
+        bufreader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, (0..100).collect::<Vec<_>>());
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 100)]
+        );
+        assert!(bufreader.fill_buf().unwrap().is_empty());
+        assert_eq!(bufreader.stream_len().unwrap(), 200);
+
+        // The rest shows up once it's cleared
+        bufreader.clear_logical_eof();
+        buf.clear();
+        bufreader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, (100..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_logical_eof_inside_buffer() {
+        let mut bufreader = SaturatingReader::new(Cursor::new((0..200).collect::<Vec<_>>()));
+        bufreader.load_all().unwrap();
+        bufreader.set_logical_eof(100);
+        bufreader.set_position(90);
+
+        // Only what's before the end is handed out, even though more is buffered
+        assert_eq!(bufreader.available(), 10);
+        let mut buf = [0; 50];
+        assert_eq!(bufreader.read_available(&mut buf), (10, None));
+        assert_eq!(&buf[..10], (90..100).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.position(), 100);
+        assert_eq!(bufreader.available(), 0);
+        assert_eq!(bufreader.read_available(&mut buf), (0, None));
+        assert_eq!(bufreader.position(), 100);
+
+        // Past the end there's nothing at all
+        bufreader.set_position(150);
+        assert_eq!(bufreader.available(), 0);
+        assert_eq!(bufreader.read_available(&mut buf), (0, None));
+        assert_eq!(bufreader.position(), 150);
+
+        // A length hint stops things in the same way
+        bufreader.clear_logical_eof();
+        bufreader.set_stream_len_hint(160);
+        assert_eq!(bufreader.available(), 10);
+    }
+
+    #[test]
+    fn test_stream_len() {
+        let reader = CountingReader::new(Cursor::new((0..200).collect::<Vec<_>>()));
+        let mut bufreader = SaturatingReader::new(reader);
+
+        let mut buf = [0; 64];
+        bufreader.read_exact(&mut buf).unwrap();
+        let seeks = bufreader.inner.seeks;
+
+        assert_eq!(bufreader.stream_len().unwrap(), 200);
+        assert_eq!(bufreader.stream_position().unwrap(), 64);
+
+        // The length is remembered after the first time
+        assert_eq!(bufreader.stream_len().unwrap(), 200);
+        assert_eq!(bufreader.inner.seeks, seeks + 1);
+
+        // Reading carries on from where it was
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (64..128).collect::<Vec<_>>().as_slice());
+
+        bufreader.rewind().unwrap();
+        assert_eq!(bufreader.stream_position().unwrap(), 0);
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..64).collect::<Vec<_>>().as_slice());
+    }
+
+    #[test]
+    fn test_buffer_store() {
+        let store = CountingStore::default();
+        let data = (0..=255).collect::<Vec<_>>();
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(16)
+            .max_cached_bytes(128)
+            .build_in(Cursor::new(data.clone()), store.clone());
+
+        // Sequential reads, merging into what's already buffered
+        let mut buf = [0; 40];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), &data[..40]);
+        let allocs = store.allocs.load(Ordering::Relaxed);
+        assert!(allocs > 0);
+
+        // Served from the store without allocating
+        bufreader.seek(SeekFrom::Start(10)).unwrap();
+        bufreader.read_exact(&mut buf[..20]).unwrap();
+        assert_eq!(&buf[..20], &data[10..30]);
+        assert_eq!(store.allocs.load(Ordering::Relaxed), allocs);
+
+        // Splitting, eviction and copies all go through the store too
+        bufreader.invalidate(20, 24);
+        bufreader.seek(SeekFrom::Start(100)).unwrap();
+        let mut rest = vec![];
+        bufreader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[100..]);
+        assert!(bufreader.cached_bytes() <= 128);
+        let clone = bufreader.clone();
+        assert_eq!(diff_caches(&bufreader, &clone), vec![]);
+
+        // Everything allocated is freed again once the readers are gone
+        drop(bufreader);
+        drop(clone);
+        assert_eq!(
+            store.allocs.load(Ordering::Relaxed),
+            store.frees.load(Ordering::Relaxed)
+        );
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        /// Fails the given number of reads with the given kind of error before reading as normal.
+        struct Flaky<R> {
+            inner: R,
+            failures: usize,
+            kind: ErrorKind,
+        }
+
+        impl<R: Read> Read for Flaky<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.failures > 0 {
+                    self.failures -= 1;
+                    return Err(self.kind.into());
+                }
+                self.inner.read(buf)
+            }
+        }
+
+        impl<R: Seek> Seek for Flaky<R> {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+
+        let flaky = |kind| Flaky {
+            inner: Cursor::new((0..=255).collect::<Vec<_>>()),
+            failures: 2,
+            kind,
+        };
+        let retry_policy = |max_retries| RetryPolicy {
+            max_retries,
+            backoff: std::time::Duration::from_millis(1),
+        };
+
+        // Enough retries to get past the failures
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(16)
+            .retry_policy(retry_policy(2))
+            .build(flaky(ErrorKind::TimedOut));
+        let mut buf = [0; 32];
+        bufreader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), (0..32).collect::<Vec<_>>().as_slice());
+        assert_eq!(bufreader.stats().misses, 1);
+
+        // Loading everything goes through the same retries
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .retry_policy(retry_policy(2))
+            .build(flaky(ErrorKind::TimedOut));
+        assert_eq!(bufreader.load_all().unwrap(), 256);
+
+        // Not enough, and not by default
+        for max_retries in [0, 1] {
+            let mut bufreader = SaturatingReaderBuilder::new()
+                .retry_policy(retry_policy(max_retries))
+                .build(flaky(ErrorKind::TimedOut));
+            assert_eq!(
+                bufreader.read(&mut buf).unwrap_err().kind(),
+                ErrorKind::TimedOut
+            );
+        }
+
+        // Errors which won't go away on their own aren't retried
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .retry_policy(retry_policy(5))
+            .build(flaky(ErrorKind::PermissionDenied));
+        assert_eq!(
+            bufreader.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            bufreader.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::PermissionDenied
+        );
+        bufreader.set_retry_policy(retry_policy(0));
+        assert_eq!(bufreader.read(&mut buf).unwrap(), 32);
+    }
+
+    #[test]
+    fn test_sequential_append() {
+        let store = CountingStore::default();
+        let data = (0..1024 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+        let mut bufreader = SaturatingReaderBuilder::new()
+            .read_size(1024)
+            .build_in(Cursor::new(data.clone()), store.clone());
+
+        let mut buf = [0; 1024];
+        for chunk in data.chunks(1024) {
+            bufreader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), chunk);
+        }
+
+        // Everything went onto the end of the first buffer, rather than each fetch being merged
+        // in with a fresh copy of all that came before
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            vec![(0, 1024 * 1024)]
+        );
+        assert_eq!(bufreader.stats().misses, 1024);
+        assert_eq!(store.allocs.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_reserve_buffers() {
+        let data = (0..10_000).map(|i| i as u8).collect::<Vec<_>>();
+        let mut bufreader = SaturatingReader::with_capacity(1, Cursor::new(data.clone()));
+        bufreader.reserve_buffers(1000);
+        let capacity = bufreader.cache.buffers.capacity();
+        assert!(capacity >= 1000);
+
+        // Fill in lots of disjoint ranges, in no particular order
+        for i in (0..1000).map(|i| i * 7 % 1000) {
+            bufreader.fetch_range(i * 10, 5).unwrap();
+        }
+        assert_eq!(bufreader.cache.buffers.len(), 1000);
+        assert_eq!(bufreader.cache.buffers.capacity(), capacity);
+        assert_eq!(
+            bufreader.cached_ranges().collect::<Vec<_>>(),
+            (0..1000).map(|i| (i * 10, i * 10 + 5)).collect::<Vec<_>>()
+        );
+        for (offset, chunk) in bufreader.cached_chunks() {
+            assert_eq!(chunk, &data[offset as usize..offset as usize + 5]);
+        }
     }
 }
@@ -0,0 +1,118 @@
+use std::{
+    io::{Read, Seek},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::saturating_reader::SaturatingReader;
+
+/// A handle to a [`SaturatingReader`] which can be shared between threads. Clones of the handle
+/// share the same reader, and so the same cache.
+///
+/// Each read holds a lock on the reader for its whole duration, including any fetches from the
+/// inner reader. This serialises reads across threads, but means a range requested by several
+/// threads at once is only fetched by whichever gets there first, with the rest finding it in
+/// the cache. If a thread panics while holding the lock, later reads will return an error.
+#[derive(Debug)]
+pub struct SharedSaturatingReader<R: Read + Seek> {
+    reader: Arc<Mutex<SaturatingReader<R>>>,
+}
+
+impl<R: Read + Seek> SharedSaturatingReader<R> {
+    pub fn new(reader: SaturatingReader<R>) -> Self {
+        Self {
+            reader: Arc::new(Mutex::new(reader)),
+        }
+    }
+
+    /// Reads from the given offset, as [`SaturatingReader::read_at`] does.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.lock()?.read_at(offset, buf)
+    }
+
+    /// Locks the reader for exclusive access, for anything that isn't covered by the methods on
+    /// the handle. Other threads will block on their reads until the guard is dropped.
+    pub fn lock(&self) -> std::io::Result<MutexGuard<'_, SaturatingReader<R>>> {
+        self.reader
+            .lock()
+            .map_err(|_| std::io::Error::other("Shared reader was poisoned."))
+    }
+}
+
+impl<R: Read + Seek> Clone for SharedSaturatingReader<R> {
+    fn clone(&self) -> Self {
+        Self {
+            reader: Arc::clone(&self.reader),
+        }
+    }
+}
+
+impl<R: Read + Seek> From<SaturatingReader<R>> for SharedSaturatingReader<R> {
+    fn from(reader: SaturatingReader<R>) -> Self {
+        Self::new(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::SharedSaturatingReader;
+    use crate::saturating_reader::SaturatingReader;
+
+    /// Wraps a reader, counting how many times it gets read from across all threads.
+    struct CountingReader<R> {
+        inner: R,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.read(buf)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_shared_read_at() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new((0..=255).collect::<Vec<_>>()),
+            reads: Arc::clone(&reads),
+        };
+        let shared = SharedSaturatingReader::new(SaturatingReader::with_capacity(1, reader));
+
+        let handles = (0..8)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let mut buf = [0; 64];
+                    let n = shared.read_at(100, &mut buf).unwrap();
+                    assert_eq!(n, 64);
+                    assert_eq!(buf.as_slice(), (100..164).collect::<Vec<_>>().as_slice());
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            shared.lock().unwrap().cached_ranges().collect::<Vec<_>>(),
+            vec![(100, 164)]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// Counters describing how well the cache is serving reads.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads which were fully satisfied from the internal buffers.
+    pub hits: u64,
+    /// Fetches from the inner reader.
+    pub misses: u64,
+    /// Bytes handed out which were already buffered before the read asked for them.
+    pub bytes_served_from_cache: u64,
+    /// The part of [`Self::bytes_served_from_cache`] handed out by reads which also had to fetch
+    /// the rest of what they asked for.
+    pub bytes_served_from_partial_hits: u64,
+    /// Bytes fetched from the inner reader, including any read ahead of what was asked for.
+    pub bytes_read_from_inner: u64,
+    /// The most that's been held in the internal buffers at once.
+    pub peak_cached_bytes: u64,
+}
+
+impl CacheStats {
+    /// Returns how many bytes the cache saved fetching from the inner reader, counting every byte
+    /// handed out from the internal buffers whether or not the rest of the read was.
+    pub fn bytes_deduplicated(&self) -> u64 {
+        self.bytes_served_from_cache
+    }
+
+    /// Returns how many bytes were handed out by reads served entirely from the internal buffers.
+    pub fn bytes_served_from_full_hits(&self) -> u64 {
+        self.bytes_served_from_cache - self.bytes_served_from_partial_hits
+    }
+
+    /// Returns the fraction of reads which were fully satisfied from the internal buffers, or zero
+    /// if nothing has been read yet.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+type DropCallback = dyn FnOnce(CacheStats) + Send + Sync;
+
+/// What to hand the final stats to when a reader is dropped, if anything. The callback only runs
+/// once, so clones start out without one.
+#[derive(Default)]
+pub(crate) struct DropHook(Option<Box<DropCallback>>);
+
+impl DropHook {
+    pub(crate) fn set(&mut self, callback: impl FnOnce(CacheStats) + Send + Sync + 'static) {
+        self.0 = Some(Box::new(callback));
+    }
+}
+
+impl Clone for DropHook {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl std::fmt::Debug for DropHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = if self.0.is_some() { "set" } else { "unset" };
+        f.debug_tuple("DropHook").field(&state).finish()
+    }
+}
+
+/// A reader's stats, which are handed to its [`DropHook`] when they're dropped along with it.
+/// Derefs to the counters themselves.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TrackedStats {
+    stats: CacheStats,
+    pub(crate) on_drop: DropHook,
+}
+
+impl Deref for TrackedStats {
+    type Target = CacheStats;
+
+    fn deref(&self) -> &CacheStats {
+        &self.stats
+    }
+}
+
+impl DerefMut for TrackedStats {
+    fn deref_mut(&mut self) -> &mut CacheStats {
+        &mut self.stats
+    }
+}
+
+impl Drop for TrackedStats {
+    fn drop(&mut self) {
+        if let Some(callback) = self.on_drop.0.take() {
+            callback(self.stats);
+        }
+    }
+}
+
+/// Something that happened in the cache, as passed to the callback given to
+/// [`crate::saturating_reader::SaturatingReader::on_event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheEvent {
+    /// `len` bytes were read from the inner reader at `offset`.
+    Fetch { offset: u64, len: u64 },
+    /// `len` bytes which were already buffered were handed out from `offset`.
+    Hit { offset: u64, len: u64 },
+    /// Buffered data was dropped to stay within the memory limit.
+    Evict { offset: u64, len: u64 },
+}
+
+type Callback = dyn FnMut(CacheEvent) + Send;
+
+/// Where to send [`CacheEvent`]s, if anywhere. Clones share the same callback.
+#[derive(Clone, Default)]
+pub(crate) struct EventHook(Option<Arc<Mutex<Callback>>>);
+
+impl EventHook {
+    pub(crate) fn set(&mut self, callback: impl FnMut(CacheEvent) + Send + 'static) {
+        self.0 = Some(Arc::new(Mutex::new(callback)));
+    }
+
+    pub(crate) fn emit(&self, event: CacheEvent) {
+        if let Some(callback) = &self.0 {
+            // A panic in the callback doesn't leave anything of ours in a bad state
+            let mut callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+            callback(event);
+        }
+    }
+}
+
+impl std::fmt::Debug for EventHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = if self.0.is_some() { "set" } else { "unset" };
+        f.debug_tuple("EventHook").field(&state).finish()
+    }
+}
@@ -0,0 +1,252 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+    buffer::{BufferStore, HeapStore},
+    cache::Cache,
+    error::SaturatingError,
+    saturating_reader::SaturatingReader,
+};
+
+/// A window onto `[offset, offset + len)` of a [`SaturatingReader`], which reads and seeks as
+/// though that range were the whole stream. Reads go through the parent's cache, and the parent's
+/// cursor is left alone.
+#[derive(Debug)]
+pub struct View<'a, R: Read + Seek, S: BufferStore = HeapStore> {
+    reader: &'a mut SaturatingReader<R, S>,
+    offset: u64,
+    len: u64,
+    // Relative to the start of the window
+    pos: u64,
+}
+
+/// Reads a [`SaturatingReader`] from the end backwards, as for formats whose index sits at the
+/// end. Everything read is buffered as usual, and the parent's cursor is left alone.
+#[derive(Debug)]
+pub struct ReverseView<'a, R: Read + Seek, S: BufferStore = HeapStore> {
+    reader: &'a mut SaturatingReader<R, S>,
+    // Everything from here onwards has been read
+    pos: u64,
+}
+
+/// Read-only access to what a [`SaturatingReader`] has buffered, created by
+/// [`SaturatingReader::cache`]. Nothing here touches the inner reader, so it only needs a shared
+/// borrow of the parent and several can be held at once. Reads through it don't count towards the
+/// stats or how recently the buffers were used.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheView<'a, S: BufferStore = HeapStore> {
+    cache: &'a Cache<S>,
+}
+
+impl<'a, S: BufferStore> CacheView<'a, S> {
+    pub(crate) fn new(cache: &'a Cache<S>) -> Self {
+        Self { cache }
+    }
+
+    /// Copies out whatever's buffered from `offset` onwards into `buf`, stopping at the first
+    /// gap. Holes are filled with zeros. Returns the number of bytes copied, which is zero if
+    /// nothing is buffered at `offset`.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> usize {
+        self.cache.read_cached(offset, buf).0
+    }
+
+    /// Returns the data from `offset` to the end of the buffer holding it, if there is one. Holes
+    /// aren't held in a buffer, so there's nothing to borrow for them; use [`Self::read_at`]
+    /// instead.
+    pub fn get(&self, offset: u64) -> Option<&'a [u8]> {
+        self.cache.get_prefix(offset)
+    }
+
+    /// Checks whether all of `[offset, offset + length)` is buffered or known to be a hole, in
+    /// which case [`Self::read_at`] can copy all of it out.
+    pub fn is_cached(&self, offset: u64, length: u64) -> bool {
+        self.cache.is_cached(offset, length)
+    }
+
+    /// Returns the ranges `[start, end)` held in the buffers, ordered by start.
+    pub fn cached_ranges(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        self.cache.cached_ranges()
+    }
+}
+
+impl<R: Read + Seek, S: BufferStore> ReverseView<'_, R, S> {
+    /// Returns the `len` bytes just before the last ones returned, starting from the end of the
+    /// stream. Fewer are returned once the start of the stream is reached, and nothing after.
+    pub fn next_back(&mut self, len: usize) -> std::io::Result<Vec<u8>> {
+        let start = self.pos.saturating_sub(len as u64);
+        let mut buf = vec![0; (self.pos - start) as usize];
+        self.reader.read_exact_at(start, &mut buf)?;
+        self.pos = start;
+
+        Ok(buf)
+    }
+
+    /// Returns the offset of the first byte returned so far, or the length of the stream if
+    /// nothing has been.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+}
+
+impl<R: Read + Seek, S: BufferStore> SaturatingReader<R, S> {
+    /// Returns a reader which works backwards from the end of the stream.
+    pub fn reverse_view(&mut self) -> std::io::Result<ReverseView<'_, R, S>> {
+        let pos = self.stream_len()?;
+
+        Ok(ReverseView { reader: self, pos })
+    }
+
+    /// Returns a reader over just `[offset, offset + len)`, as for handing part of the stream to
+    /// something which expects to see all of it. Reads past the end of the window find nothing.
+    pub fn view(&mut self, offset: u64, len: u64) -> View<'_, R, S> {
+        View {
+            reader: self,
+            offset,
+            len,
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read + Seek, S: BufferStore> Read for View<'_, R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let len = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        // Past the end of the window, where the offset may not even fit
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let num_bytes_read = self
+            .reader
+            .read_at(self.offset + self.pos, &mut buf[..len])?;
+        self.pos += num_bytes_read as u64;
+
+        Ok(num_bytes_read)
+    }
+}
+
+impl<R: Read + Seek, S: BufferStore> Seek for View<'_, R, S> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(p) => {
+                self.pos = p;
+                return Ok(p);
+            }
+            SeekFrom::Current(p) => (self.pos, p),
+            SeekFrom::End(p) => (self.len, p),
+        };
+        self.pos = base
+            .checked_add_signed(offset)
+            .ok_or(SaturatingError::SeekUnderflow {
+                position: base,
+                offset,
+            })?;
+
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use crate::saturating_reader::SaturatingReader;
+
+    #[test]
+    fn test_reverse_view() {
+        let mut bufreader =
+            SaturatingReader::with_capacity(4, Cursor::new((0..=255).collect::<Vec<_>>()));
+
+        let mut view = bufreader.reverse_view().unwrap();
+        assert_eq!(view.next_back(10).unwrap(), (246..=255).collect::<Vec<_>>());
+        assert_eq!(view.next_back(10).unwrap(), (236..246).collect::<Vec<_>>());
+        assert_eq!(view.position(), 236);
+        assert!(bufreader.is_cached(236, 20));
+        assert_eq!(bufreader.position(), 0);
+
+        // Running into the start of the stream
+        let mut view = bufreader.reverse_view().unwrap();
+        assert_eq!(view.next_back(250).unwrap().len(), 250);
+        assert_eq!(view.next_back(10).unwrap(), (0..6).collect::<Vec<_>>());
+        assert!(view.next_back(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_view() {
+        let mut bufreader =
+            SaturatingReader::with_capacity(16, Cursor::new((0..=255).collect::<Vec<_>>()));
+        bufreader.fetch_range(10, 20).unwrap();
+
+        // Several views can be read from while something else holds on to the reader
+        let reader = &bufreader;
+        let (view1, view2) = (reader.cache(), reader.cache());
+        let mut buf = [0; 30];
+        assert_eq!(view1.read_at(20, &mut buf), 10);
+        assert_eq!(&buf[..10], (20..30).collect::<Vec<_>>().as_slice());
+        assert_eq!(view2.read_at(0, &mut buf), 0);
+        assert_eq!(view2.get(25), Some([25, 26, 27, 28, 29].as_slice()));
+        assert!(view1.is_cached(10, 20));
+        assert!(!view1.is_cached(10, 21));
+        assert_eq!(view2.cached_ranges().collect::<Vec<_>>(), vec![(10, 30)]);
+        assert_eq!(reader.position(), 0);
+
+        // Nothing was counted
+        assert_eq!(bufreader.stats().bytes_served_from_cache, 0);
+    }
+
+    #[test]
+    fn test_cache_view_hole() {
+        let mut bufreader =
+            SaturatingReader::with_capacity(16, Cursor::new((0..=255).collect::<Vec<_>>()));
+        bufreader.fetch_range(10, 20).unwrap();
+        bufreader.mark_hole(30, 50);
+
+        // The view and the reader agree that the hole is covered, and it reads as zeros
+        let view = bufreader.cache();
+        assert!(view.is_cached(10, 40));
+        assert!(bufreader.missing_ranges(10, 40).is_empty());
+        assert_eq!(bufreader.coverage_fraction(10, 40), 1.0);
+        let mut buf = [1; 40];
+        assert_eq!(view.read_at(10, &mut buf), 40);
+        assert_eq!(&buf[..20], (10..30).collect::<Vec<_>>().as_slice());
+        assert_eq!(&buf[20..], &[0; 20]);
+
+        // Just past it, both agree there's a gap
+        assert!(!view.is_cached(40, 20));
+        assert_eq!(bufreader.missing_ranges(40, 20), vec![(50, 60)]);
+        assert_eq!(bufreader.coverage_fraction(40, 20), 0.5);
+        assert_eq!(view.read_at(40, &mut buf[..20]), 10);
+    }
+
+    #[test]
+    fn test_view() {
+        let mut bufreader =
+            SaturatingReader::with_capacity(16, Cursor::new((0..=255).collect::<Vec<_>>()));
+        bufreader.fetch_range(90, 20).unwrap();
+
+        let mut view = bufreader.view(100, 50);
+        let mut buf = vec![];
+        view.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, (100..150).collect::<Vec<_>>());
+
+        // Seeking is relative to the window
+        assert_eq!(view.seek(SeekFrom::End(-10)).unwrap(), 40);
+        let mut buf = [0; 20];
+        assert_eq!(view.read(&mut buf).unwrap(), 10);
+        assert_eq!(&buf[..10], (140..150).collect::<Vec<_>>().as_slice());
+        view.seek(SeekFrom::Start(5)).unwrap();
+        view.read_exact(&mut buf[..5]).unwrap();
+        assert_eq!(&buf[..5], &[105, 106, 107, 108, 109]);
+        assert!(view.seek(SeekFrom::Current(-20)).is_err());
+        view.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(view.read(&mut buf).unwrap(), 0);
+        view.seek(SeekFrom::Start(u64::MAX)).unwrap();
+        assert_eq!(view.read(&mut buf).unwrap(), 0);
+
+        // The parent's cursor hasn't moved, and it kept what the view fetched
+        assert_eq!(bufreader.position(), 0);
+        assert!(bufreader.is_cached(90, 60));
+    }
+}